@@ -1,15 +1,41 @@
+pub mod aligned_pair;
+pub mod alignment_plan;
+pub mod config;
 mod distance_alignment;
-mod semantic_alignment;
-mod text_processor;
+pub mod evaluation;
+pub mod multi_alignment;
+pub mod overlap_clusters;
+pub mod semantic_alignment;
+pub(crate) mod speaker;
+pub mod sync_detection;
+pub mod synthetic;
+pub(crate) mod text_processor;
+mod timing;
 
 use assa_parse::assa_file::event::Event;
+use serde::{Deserialize, Serialize};
 use std::cmp::min;
 
+use aligned_pair::{add_unmatched_pairs, order_pairs, score_pairs, AlignedPair};
+use config::AlignmentConfig;
 use distance_alignment::text_distance_alignment;
+use overlap_clusters::cluster_order;
 use semantic_alignment::semantic_alignment;
-use semantic_alignment::semantic_similarity::SemanticSimilarity;
+use semantic_alignment::semantic_similarity::SimilarityBackend;
+use sync_detection::{closest_modified_index, OffsetMap};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlignmentMode {
+    /// Both files are in the same language, lines are matched on text
+    /// distance first and on meaning second.
+    Monolingual,
+    /// The files are in different languages. The text distance stage is
+    /// skipped and lines are matched on timing plus the meaning according to
+    /// a multilingual embedding model.
+    CrossLingual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum AlignmentAction {
     None,
     Merge,
@@ -29,61 +55,395 @@ pub struct ComparisonContext<'a> {
     modified_events: &'a Vec<&'a Event>,
     lookahead: usize,
     prev_alignment_action: &'a mut AlignmentAction,
+    offset_map: &'a OffsetMap,
+    mode: AlignmentMode,
+    config: &'a AlignmentConfig,
 }
 
 pub fn align_events(
     original_events: &Vec<&Event>,
     modified_events: &Vec<&Event>,
+    offset_map: &OffsetMap,
+    semantic_similarity: &dyn SimilarityBackend,
+    mode: AlignmentMode,
+    config: &AlignmentConfig,
     lookahead_range: usize,
-) {
+) -> Vec<AlignedPair> {
+    // Nothing to compare with, every event of the other side is unmatched
+    if original_events.is_empty() || modified_events.is_empty() {
+        let mut aligned_pairs = Vec::new();
+        add_unmatched_pairs(
+            &mut aligned_pairs,
+            original_events.len(),
+            modified_events.len(),
+        );
+        return aligned_pairs;
+    }
+
+    // Overlapping events are aligned in the order of the original file, the
+    // pairs are mapped back to the modified events at the end
+    let order = cluster_order(
+        original_events,
+        modified_events,
+        offset_map,
+        semantic_similarity,
+        config,
+    );
+    let clustered_events: Vec<&Event> = order.iter().map(|&i| modified_events[i]).collect();
+
     let original_max_index = original_events.len() - 1;
-    let modified_max_index = modified_events.len() - 1;
+    let modified_max_index = clustered_events.len() - 1;
     let mut prev_alignment_action = AlignmentAction::None;
-
-    let semantic_similarity = SemanticSimilarity::default();
+    let mut aligned_pairs: Vec<AlignedPair> = Vec::with_capacity(original_events.len());
 
     let mut offset: i32 = 0;
     let mut prev_offset: i32 = 0;
     let mut comparison_loop_index = 0;
-    'comparison_loop: while comparison_loop_index < original_max_index {
-        println!(
-            // Debug
-            "{} || {} ==== {} || {}",
-            comparison_loop_index,
-            offset,
-            &original_events[comparison_loop_index].text,
-            &modified_events[(comparison_loop_index as i32 + offset) as usize].text
-        );
+    let mut sync_segment_index = 0;
+    while comparison_loop_index < original_max_index {
+        // Entering a new sync segment means a scene was cut or inserted, so the
+        // index offset is re-anchored on the timing of that segment.
+        let original_event = original_events[comparison_loop_index];
+        let segment_index = offset_map.segment_index_at(original_event.start);
+        if segment_index != sync_segment_index {
+            sync_segment_index = segment_index;
+            if let Some(modified_index) = closest_modified_index(
+                original_event,
+                &clustered_events,
+                offset_map.offset_at(original_event.start),
+            ) {
+                prev_offset = offset;
+                offset = modified_index as i32 - comparison_loop_index as i32;
+                prev_alignment_action = AlignmentAction::None;
+            }
+        }
 
         if comparison_loop_index as i32 + offset > modified_max_index as i32 {
+            aligned_pairs.push(AlignedPair::new(
+                vec![comparison_loop_index],
+                vec![],
+                AlignmentAction::None,
+            ));
             comparison_loop_index += 1;
-            continue 'comparison_loop;
+            continue;
         }
 
         let lookahead = min(lookahead_range, original_max_index);
+        let step_index = comparison_loop_index;
+        let step_offset = offset;
 
         let mut comparison_context = ComparisonContext {
             comparison_loop_index: &mut comparison_loop_index,
             offset: &mut offset,
             prev_offset: &mut prev_offset,
             original_events,
-            modified_events,
+            modified_events: &clustered_events,
             lookahead,
             prev_alignment_action: &mut prev_alignment_action,
+            offset_map,
+            mode,
+            config,
         };
 
-        let (distance_took_actions, _) = text_distance_alignment(&mut comparison_context, false);
-        if distance_took_actions {
-            continue 'comparison_loop;
+        let took_actions = (mode == AlignmentMode::Monolingual
+            && text_distance_alignment(&mut comparison_context).0)
+            || semantic_alignment(&mut comparison_context, semantic_similarity).0;
+        if !took_actions {
+            *comparison_context.prev_alignment_action = AlignmentAction::None;
+            *comparison_context.comparison_loop_index += 1;
+        }
+
+        record_step(
+            &mut aligned_pairs,
+            (step_index, step_offset),
+            (comparison_loop_index, offset),
+            prev_alignment_action,
+            clustered_events.len(),
+        );
+    }
+
+    let mut aligned_pairs =
+        complete_pairs(aligned_pairs, original_events.len(), clustered_events.len());
+    for pair in &mut aligned_pairs {
+        pair.modified_indices = pair.modified_indices.iter().map(|&i| order[i]).collect();
+        pair.modified_indices.sort();
+    }
+    order_pairs(&mut aligned_pairs);
+    score_pairs(
+        &mut aligned_pairs,
+        original_events,
+        modified_events,
+        offset_map,
+        semantic_similarity,
+        mode,
+        config,
+    );
+    aligned_pairs
+}
+
+// Turns the change of the loop index and offset during one step of the
+// comparison loop into the events that were aligned with each other.
+fn record_step(
+    aligned_pairs: &mut Vec<AlignedPair>,
+    (index, offset): (usize, i32),
+    (next_index, next_offset): (usize, i32),
+    action: AlignmentAction,
+    modified_count: usize,
+) {
+    let modified_start = index as i32 + offset;
+
+    // The last line of the previous split actually belongs to this line
+    if action == AlignmentAction::Prev {
+        let modified_index = (modified_start - 1) as usize;
+        if let Some(prev_pair) = aligned_pairs.last_mut() {
+            prev_pair.modified_indices.retain(|&i| i != modified_index);
         }
+        aligned_pairs.push(AlignedPair::new(vec![index], vec![modified_index], action));
+        return;
+    }
+
+    let original_indices: Vec<usize> = (index..next_index).collect();
+    let modified_indices: Vec<usize> = (modified_start..next_index as i32 + next_offset)
+        .filter(|&i| i >= 0 && (i as usize) < modified_count)
+        .map(|i| i as usize)
+        .collect();
+
+    // Sentences that were split the same way in both files are kept as
+    // separate one-to-one pairs.
+    if action == AlignmentAction::None
+        && original_indices.len() > 1
+        && original_indices.len() == modified_indices.len()
+    {
+        for (original_index, modified_index) in original_indices.into_iter().zip(modified_indices) {
+            aligned_pairs.push(AlignedPair::new(
+                vec![original_index],
+                vec![modified_index],
+                action,
+            ));
+        }
+        return;
+    }
+
+    aligned_pairs.push(AlignedPair::new(original_indices, modified_indices, action));
+}
+
+// Adds the events the comparison loop never reached or skipped over as
+// unmatched pairs, and orders all pairs along the modified events.
+fn complete_pairs(
+    mut aligned_pairs: Vec<AlignedPair>,
+    original_count: usize,
+    modified_count: usize,
+) -> Vec<AlignedPair> {
+    // The comparison loop stops before the last original line
+    let last_original = original_count - 1;
+    let next_modified = aligned_pairs
+        .iter()
+        .flat_map(|pair| pair.modified_indices.iter())
+        .max()
+        .map_or(0, |&i| i + 1);
+    let last_original_used = aligned_pairs
+        .iter()
+        .any(|pair| pair.original_indices.contains(&last_original));
+    if !last_original_used && next_modified < modified_count {
+        aligned_pairs.push(AlignedPair::new(
+            vec![last_original],
+            vec![next_modified],
+            AlignmentAction::None,
+        ));
+    }
+
+    add_unmatched_pairs(&mut aligned_pairs, original_count, modified_count);
+    order_pairs(&mut aligned_pairs);
+    aligned_pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::evaluation::Evaluation;
+    use super::semantic_alignment::lexical_similarity::LexicalSimilarity;
+    use super::sync_detection::detect_sync_segments;
+    use super::synthetic::{synthesize_pair, Perturbation};
+    use super::*;
+    use crate::test_helpers::dialogue_lines;
+    use assa_parse::assa_file::AssaFile;
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use std::str::FromStr;
+
+    const SCRIPT: &str = r"[Script Info]
+ScriptType: v4.00+
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:01.00,0:00:04.00,Default,,0,0,0,,Wake up, it's already morning.
+Dialogue: 0,0:00:05.00,0:00:08.00,Default,,0,0,0,,I don't want to go to school today.
+Dialogue: 0,0:00:09.00,0:00:12.00,Default,,0,0,0,,You say that every single day.
+Dialogue: 0,0:00:13.00,0:00:16.00,Default,,0,0,0,,Because it's true every single day!
+Dialogue: 0,0:00:17.00,0:00:20.00,Default,,0,0,0,,Hurry up or we'll miss the train.
+Dialogue: 0,0:00:21.00,0:00:24.00,Default,,0,0,0,,Fine, I'm getting dressed. Give me five minutes.
+Dialogue: 0,0:00:25.00,0:00:27.00,Default,,0,0,0,,Did you pack your lunch?
+Dialogue: 0,0:00:28.00,0:00:31.00,Default,,0,0,0,,Mom made rice balls again, with plum inside.
+Dialogue: 0,0:00:33.00,0:00:36.00,Default,,0,0,0,,The station is packed this morning.
+Dialogue: 0,0:00:37.00,0:00:40.00,Default,,0,0,0,,Stay close, I don't want to lose you in the crowd.
+Dialogue: 0,0:00:41.00,0:00:43.00,Default,,0,0,0,,The express leaves from platform three.
+Dialogue: 0,0:00:44.00,0:00:47.00,Default,,0,0,0,,We made it! Barely, but we made it.
+Dialogue: 0,0:00:48.00,0:00:51.00,Default,,0,0,0,,Who is that girl by the door?
+Dialogue: 0,0:00:52.00,0:00:55.00,Default,,0,0,0,,She transferred to our class last week.
+Dialogue: 0,0:00:56.00,0:00:59.00,Default,,0,0,0,,I heard she lived abroad for years.
+Dialogue: 0,0:01:00.00,0:01:03.00,Default,,0,0,0,,Go talk to her, she looks lonely.
+Dialogue: 0,0:01:04.00,0:01:07.00,Default,,0,0,0,,Not now, the teacher is watching us.
+Dialogue: 0,0:01:08.00,0:01:11.00,Default,,0,0,0,,After school then. Promise me.
+Dialogue: 0,0:01:12.00,0:01:15.00,Default,,0,0,0,,Fine, I promise. Happy now?
+Dialogue: 0,0:01:16.00,0:01:19.00,Default,,0,0,0,,Very. See you at the gate after class.";
+
+    #[test]
+    fn align_events_covers_every_event_once() {
+        let original = dialogue_lines(&[
+            "Wake up, it's already morning.",
+            "I don't want to go to school today.",
+            "You say that every single day.",
+            "Because it's true every single day!",
+            "Hurry up or we'll miss the train.",
+        ]);
+        let modified = dialogue_lines(&[
+            "Wake up, it's already morning.",
+            "I don't want to go to school today.",
+            "You say that every day.",
+            "Because it's true!",
+            "What's for breakfast?",
+            "Hurry up or we'll miss the train.",
+        ]);
+        let original: Vec<&Event> = original.iter().collect();
+        let modified: Vec<&Event> = modified.iter().collect();
+        let config = AlignmentConfig::default();
+        let offset_map = detect_sync_segments(&original, &modified, &config);
+
+        let pairs = align_events(
+            &original,
+            &modified,
+            &offset_map,
+            &LexicalSimilarity::default(),
+            AlignmentMode::Monolingual,
+            &config,
+            4,
+        );
+
+        let mut original_indices: Vec<usize> = pairs
+            .iter()
+            .flat_map(|pair| pair.original_indices.clone())
+            .collect();
+        let mut modified_indices: Vec<usize> = pairs
+            .iter()
+            .flat_map(|pair| pair.modified_indices.clone())
+            .collect();
+        original_indices.sort();
+        modified_indices.sort();
+        assert_eq!(original_indices, (0..original.len()).collect::<Vec<_>>());
+        assert_eq!(modified_indices, (0..modified.len()).collect::<Vec<_>>());
+
+        assert_eq!(pairs[0].original_indices, vec![0]);
+        assert_eq!(pairs[0].modified_indices, vec![0]);
+        assert!(pairs[0].confidence > 0.9);
+        assert!(pairs
+            .iter()
+            .filter(|pair| !pair.is_matched())
+            .all(|pair| pair.confidence == 0f64));
+    }
+
+    #[test]
+    fn align_events_stops_at_the_end_of_the_scripts() {
+        // The last lines have more sentence parts than lines are left
+        let original = dialogue_lines(&[
+            "Wake up, it's already morning.",
+            "Wait, what? No, five more minutes, please!",
+            "Fine.",
+        ]);
+        let modified = dialogue_lines(&[
+            "Wake up, it's already morning.",
+            "Wait, what? No, five more minutes, please!",
+        ]);
+        let original: Vec<&Event> = original.iter().collect();
+        let modified: Vec<&Event> = modified.iter().collect();
+        let config = AlignmentConfig::default();
+        let offset_map = detect_sync_segments(&original, &modified, &config);
+
+        let pairs = align_events(
+            &original,
+            &modified,
+            &offset_map,
+            &LexicalSimilarity::default(),
+            AlignmentMode::Monolingual,
+            &config,
+            4,
+        );
+        assert_eq!(pairs[1].original_indices, vec![1]);
+        assert_eq!(pairs[1].modified_indices, vec![1]);
+    }
+
+    #[test]
+    fn align_events_with_an_empty_side() {
+        let lines = dialogue_lines(&["Wake up, it's already morning."]);
+        let lines: Vec<&Event> = lines.iter().collect();
+        let config = AlignmentConfig::default();
+        let offset_map = OffsetMap { segments: vec![] };
+        let align = |original: &Vec<&Event>, modified: &Vec<&Event>| {
+            align_events(
+                original,
+                modified,
+                &offset_map,
+                &LexicalSimilarity::default(),
+                AlignmentMode::Monolingual,
+                &config,
+                4,
+            )
+        };
+
+        let pairs = align(&lines, &vec![]);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].original_indices, vec![0]);
+        assert!(pairs[0].modified_indices.is_empty());
+
+        let pairs = align(&vec![], &lines);
+        assert_eq!(pairs.len(), 1);
+        assert!(pairs[0].original_indices.is_empty());
+        assert_eq!(pairs[0].modified_indices, vec![0]);
+    }
+
+    #[test]
+    fn align_events_on_synthetic_pairs() {
+        let source = AssaFile::from_str(SCRIPT).unwrap();
+        let indices: Vec<usize> = (0..source.events.len()).collect();
+        let mut f1_scores: Vec<f64> = Vec::new();
+        for seed in 0..10 {
+            let synthetic = synthesize_pair(
+                &source,
+                &indices,
+                &Perturbation::default(),
+                &mut ChaCha8Rng::seed_from_u64(seed),
+            );
+            let original: Vec<&Event> = source.events.iter().collect();
+            let modified: Vec<&Event> = synthetic.file.events.iter().collect();
+            let config = AlignmentConfig::default();
+            let offset_map = detect_sync_segments(&original, &modified, &config);
 
-        let (semantic_took_actions, _) =
-            semantic_alignment(&mut comparison_context, &semantic_similarity);
-        if semantic_took_actions {
-            continue 'comparison_loop;
+            let pairs = align_events(
+                &original,
+                &modified,
+                &offset_map,
+                &LexicalSimilarity::default(),
+                AlignmentMode::Monolingual,
+                &config,
+                4,
+            );
+            f1_scores.push(Evaluation::new(&pairs, &synthetic.pairs).total().f1());
         }
 
-        *comparison_context.prev_alignment_action = AlignmentAction::None;
-        *comparison_context.comparison_loop_index += 1;
+        // Regression floor, raise it when the aligner gets better
+        let mean_f1 = f1_scores.iter().sum::<f64>() / f1_scores.len() as f64;
+        assert!(mean_f1 >= 0.75, "F1 per seed {:?}", f1_scores);
     }
 }
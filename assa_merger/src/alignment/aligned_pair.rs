@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+
+use assa_parse::assa_file::event::Event;
+use serde::{Deserialize, Serialize};
+
+use super::config::AlignmentConfig;
+use super::semantic_alignment::semantic_similarity::SimilarityBackend;
+use super::sync_detection::OffsetMap;
+use super::text_processor::{levenshtein_ratio, prep_for_value_measuring};
+use super::timing::timing_similarity;
+use super::{AlignmentAction, AlignmentMode};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AlignmentScores {
+    pub levenshtein: f64,
+    pub semantic: f64,
+    pub timing: f64,
+}
+
+/// A group of original events that was matched with a group of modified
+/// events. One side is empty for lines that only exist in one of the files.
+#[derive(Debug, Clone)]
+pub struct AlignedPair {
+    pub original_indices: Vec<usize>,
+    pub modified_indices: Vec<usize>,
+    pub action: AlignmentAction,
+    pub scores: AlignmentScores,
+    /// Aggregate of the scores between 0 and 1, unmatched lines get 0
+    pub confidence: f64,
+    /// Manual correction that takes precedence over the aligner
+    pub pinned: bool,
+}
+
+impl AlignedPair {
+    pub fn new(
+        original_indices: Vec<usize>,
+        modified_indices: Vec<usize>,
+        action: AlignmentAction,
+    ) -> Self {
+        Self {
+            original_indices,
+            modified_indices,
+            action,
+            scores: AlignmentScores::default(),
+            confidence: 0f64,
+            pinned: false,
+        }
+    }
+
+    pub fn is_matched(&self) -> bool {
+        !self.original_indices.is_empty() && !self.modified_indices.is_empty()
+    }
+}
+
+pub fn score_pairs(
+    pairs: &mut [AlignedPair],
+    original_events: &[&Event],
+    modified_events: &[&Event],
+    offset_map: &OffsetMap,
+    semantic_similarity: &dyn SimilarityBackend,
+    mode: AlignmentMode,
+    config: &AlignmentConfig,
+) {
+    for pair in pairs.iter_mut().filter(|pair| pair.is_matched()) {
+        let originals: Vec<&Event> = pair
+            .original_indices
+            .iter()
+            .map(|&i| original_events[i])
+            .collect();
+        let modifieds: Vec<&Event> = pair
+            .modified_indices
+            .iter()
+            .map(|&i| modified_events[i])
+            .collect();
+        let original_text = join_event_text(&originals);
+        let modified_text = join_event_text(&modifieds);
+
+        pair.scores = AlignmentScores {
+            levenshtein: levenshtein_ratio(&original_text, &modified_text),
+            semantic: semantic_similarity.compare(
+                &prep_for_value_measuring(&original_text),
+                &prep_for_value_measuring(&modified_text),
+            ),
+            timing: timing_similarity(
+                &originals,
+                &modifieds,
+                offset_map.offset_at(originals[0].start),
+            ),
+        };
+
+        // The levenshtein ratio means nothing across languages
+        let text_score = match mode {
+            AlignmentMode::Monolingual => pair.scores.levenshtein.max(pair.scores.semantic),
+            AlignmentMode::CrossLingual => pair.scores.semantic,
+        };
+        pair.confidence = match pair.pinned {
+            true => 1f64,
+            false => ((1f64 - config.confidence_timing_weight) * text_score
+                + config.confidence_timing_weight * pair.scores.timing)
+                .clamp(0f64, 1f64),
+        };
+    }
+}
+
+/// Adds a one-sided pair for every event that is not part of any pair yet.
+pub fn add_unmatched_pairs(
+    pairs: &mut Vec<AlignedPair>,
+    original_count: usize,
+    modified_count: usize,
+) {
+    let mut original_used = vec![false; original_count];
+    let mut modified_used = vec![false; modified_count];
+    for pair in pairs.iter() {
+        pair.original_indices
+            .iter()
+            .for_each(|&i| original_used[i] = true);
+        pair.modified_indices
+            .iter()
+            .for_each(|&i| modified_used[i] = true);
+    }
+
+    for original_index in (0..original_count).filter(|&i| !original_used[i]) {
+        pairs.push(AlignedPair::new(
+            vec![original_index],
+            vec![],
+            AlignmentAction::None,
+        ));
+    }
+    for modified_index in (0..modified_count).filter(|&i| !modified_used[i]) {
+        pairs.push(AlignedPair::new(
+            vec![],
+            vec![modified_index],
+            AlignmentAction::Next,
+        ));
+    }
+}
+
+/// Orders the pairs along the modified events. Lines that only exist in the
+/// original go right after the pair of the original line before them.
+pub fn order_pairs(pairs: &mut [AlignedPair]) {
+    let mut modified_position: HashMap<usize, f64> = HashMap::new();
+    for pair in pairs.iter() {
+        if let Some(&modified_index) = pair.modified_indices.iter().min() {
+            for &original_index in &pair.original_indices {
+                modified_position.insert(original_index, modified_index as f64);
+            }
+        }
+    }
+
+    let sort_key = |pair: &AlignedPair| -> (f64, usize) {
+        let original_index = pair.original_indices.iter().min().copied().unwrap_or(0);
+        match pair.modified_indices.iter().min() {
+            Some(&modified_index) => (modified_index as f64, original_index),
+            None => (
+                (0..original_index)
+                    .rev()
+                    .find_map(|i| modified_position.get(&i))
+                    .map_or(-0.5, |position| position + 0.5),
+                original_index,
+            ),
+        }
+    };
+    pairs.sort_by(|a, b| {
+        let (a_key, b_key) = (sort_key(a), sort_key(b));
+        a_key.0.total_cmp(&b_key.0).then(a_key.1.cmp(&b_key.1))
+    });
+}
+
+/// Replaces the pairs of the aligner by the pinned pairs wherever they share
+/// an event. Lines that lose their counterpart are left unmatched, the
+/// changed pairs have to be scored again.
+pub fn pin_pairs(pairs: Vec<AlignedPair>, pinned_pairs: Vec<AlignedPair>) -> Vec<AlignedPair> {
+    let pinned_original: HashSet<usize> = pinned_pairs
+        .iter()
+        .flat_map(|pair| pair.original_indices.iter().copied())
+        .collect();
+    let pinned_modified: HashSet<usize> = pinned_pairs
+        .iter()
+        .flat_map(|pair| pair.modified_indices.iter().copied())
+        .collect();
+
+    let mut result: Vec<AlignedPair> = Vec::with_capacity(pairs.len());
+    for mut pair in pairs {
+        let pair_size = pair.original_indices.len() + pair.modified_indices.len();
+        pair.original_indices
+            .retain(|i| !pinned_original.contains(i));
+        pair.modified_indices
+            .retain(|i| !pinned_modified.contains(i));
+        if pair.original_indices.len() + pair.modified_indices.len() == pair_size {
+            result.push(pair);
+            continue;
+        }
+
+        if pair.is_matched() {
+            result.push(AlignedPair::new(
+                pair.original_indices,
+                pair.modified_indices,
+                pair.action,
+            ));
+            continue;
+        }
+        for original_index in pair.original_indices {
+            result.push(AlignedPair::new(
+                vec![original_index],
+                vec![],
+                AlignmentAction::None,
+            ));
+        }
+        for modified_index in pair.modified_indices {
+            result.push(AlignedPair::new(
+                vec![],
+                vec![modified_index],
+                AlignmentAction::Next,
+            ));
+        }
+    }
+
+    result.extend(pinned_pairs.into_iter().map(|mut pair| {
+        pair.pinned = true;
+        pair
+    }));
+    order_pairs(&mut result);
+    result
+}
+
+fn join_event_text(events: &[&Event]) -> String {
+    events
+        .iter()
+        .map(|event| event.text.as_str())
+        .collect::<Vec<&str>>()
+        .join(" ")
+}
@@ -0,0 +1,276 @@
+// Alignment plans
+//
+// Stores the result of an alignment as JSON so it can be applied again
+// without computing any similarities, e.g.
+//
+// {
+//   "pairs": [
+//     {
+//       "dialogue": [{ "index": 12, "hash": "3f0c9e6a1b27d845" }],
+//       "base": [{ "index": 15, "hash": "9a41d0c3e7f28b56" }],
+//       "action": "None",
+//       "confidence": 0.93,
+//       "pinned": true
+//     }
+//   ]
+// }
+//
+// Events are referenced by their index in the (sorted) script and a hash of
+// their style and text. When a script was edited and the event at an index no
+// longer has the same hash, the nearest event with that hash is used instead.
+// Pairs marked as pinned are manual corrections, they are kept when the
+// alignment is run again.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use assa_parse::assa_file::event::Event;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::aligned_pair::{add_unmatched_pairs, order_pairs, AlignedPair, AlignmentScores};
+use super::text_processor::fnv1a;
+use super::AlignmentAction;
+
+#[derive(Error, Debug)]
+pub enum AlignmentPlanError {
+    #[error("could not access alignment plan file")]
+    IoError(std::io::Error),
+    #[error("malformed alignment plan file")]
+    FormatError(serde_json::Error),
+    #[error("{0} event {1} of the alignment plan was not found in the script")]
+    MissingEvent(&'static str, usize),
+}
+
+impl From<std::io::Error> for AlignmentPlanError {
+    fn from(error: std::io::Error) -> Self {
+        AlignmentPlanError::IoError(error)
+    }
+}
+
+impl From<serde_json::Error> for AlignmentPlanError {
+    fn from(error: serde_json::Error) -> Self {
+        AlignmentPlanError::FormatError(error)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRef {
+    /// Position of the event in the script
+    pub index: usize,
+    pub hash: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedPair {
+    pub dialogue: Vec<EventRef>,
+    pub base: Vec<EventRef>,
+    pub action: AlignmentAction,
+    #[serde(default)]
+    pub scores: AlignmentScores,
+    #[serde(default)]
+    pub confidence: f64,
+    #[serde(default)]
+    pub pinned: bool,
+}
+
+/// The events of a script that take part in the alignment, `indices` maps the
+/// positions used by the aligner to positions in `events`.
+#[derive(Clone, Copy)]
+pub struct ScriptSelection<'a> {
+    pub events: &'a [Event],
+    pub indices: &'a [usize],
+}
+
+impl ScriptSelection<'_> {
+    fn event_ref(&self, position: usize) -> EventRef {
+        let index = self.indices[position];
+        EventRef {
+            index,
+            hash: event_hash(&self.events[index]),
+        }
+    }
+
+    fn resolve(&self, event_ref: &EventRef) -> Option<usize> {
+        if let Ok(position) = self.indices.binary_search(&event_ref.index) {
+            if event_hash(&self.events[event_ref.index]) == event_ref.hash {
+                return Some(position);
+            }
+        }
+
+        self.indices
+            .iter()
+            .enumerate()
+            .filter(|(_, &index)| event_hash(&self.events[index]) == event_ref.hash)
+            .min_by_key(|(_, &index)| index.abs_diff(event_ref.index))
+            .map(|(position, _)| position)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AlignmentPlan {
+    pub pairs: Vec<PlannedPair>,
+}
+
+impl AlignmentPlan {
+    pub fn from_pairs(
+        pairs: &[AlignedPair],
+        dialogue: &ScriptSelection,
+        base: &ScriptSelection,
+    ) -> Self {
+        Self {
+            pairs: pairs
+                .iter()
+                .map(|pair| PlannedPair {
+                    dialogue: pair
+                        .original_indices
+                        .iter()
+                        .map(|&i| dialogue.event_ref(i))
+                        .collect(),
+                    base: pair
+                        .modified_indices
+                        .iter()
+                        .map(|&i| base.event_ref(i))
+                        .collect(),
+                    action: pair.action,
+                    scores: pair.scores.clone(),
+                    confidence: pair.confidence,
+                    pinned: pair.pinned,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn load_file(path: &Path) -> Result<Self, AlignmentPlanError> {
+        Ok(serde_json::from_reader(BufReader::new(File::open(path)?))?)
+    }
+
+    pub fn save_file(&self, path: &Path) -> Result<(), AlignmentPlanError> {
+        serde_json::to_writer_pretty(BufWriter::new(File::create(path)?), self)?;
+        Ok(())
+    }
+
+    /// Turns the plan back into aligned pairs, with the scores stored in the
+    /// plan. Events that were added to the scripts since the plan was saved
+    /// end up unmatched.
+    pub fn apply(
+        &self,
+        dialogue: &ScriptSelection,
+        base: &ScriptSelection,
+    ) -> Result<Vec<AlignedPair>, AlignmentPlanError> {
+        let mut pairs: Vec<AlignedPair> = self
+            .pairs
+            .iter()
+            .map(|planned_pair| resolve_pair(planned_pair, dialogue, base))
+            .collect::<Result<_, _>>()?;
+
+        add_unmatched_pairs(&mut pairs, dialogue.indices.len(), base.indices.len());
+        order_pairs(&mut pairs);
+        Ok(pairs)
+    }
+
+    /// Resolves the pinned pairs against the (possibly edited) scripts. Pins
+    /// of which an event was removed are returned as errors.
+    pub fn pinned_pairs(
+        &self,
+        dialogue: &ScriptSelection,
+        base: &ScriptSelection,
+    ) -> (Vec<AlignedPair>, Vec<AlignmentPlanError>) {
+        let mut pinned_pairs: Vec<AlignedPair> = Vec::new();
+        let mut errors: Vec<AlignmentPlanError> = Vec::new();
+        for planned_pair in self.pairs.iter().filter(|pair| pair.pinned) {
+            match resolve_pair(planned_pair, dialogue, base) {
+                Ok(pair) => pinned_pairs.push(pair),
+                Err(error) => errors.push(error),
+            }
+        }
+        (pinned_pairs, errors)
+    }
+}
+
+fn resolve_pair(
+    planned_pair: &PlannedPair,
+    dialogue: &ScriptSelection,
+    base: &ScriptSelection,
+) -> Result<AlignedPair, AlignmentPlanError> {
+    let resolve_refs = |script: &ScriptSelection, event_refs: &[EventRef], side: &'static str| {
+        event_refs
+            .iter()
+            .map(|event_ref| {
+                script
+                    .resolve(event_ref)
+                    .ok_or(AlignmentPlanError::MissingEvent(side, event_ref.index))
+            })
+            .collect::<Result<Vec<usize>, _>>()
+    };
+
+    let mut pair = AlignedPair::new(
+        resolve_refs(dialogue, &planned_pair.dialogue, "dialogue")?,
+        resolve_refs(base, &planned_pair.base, "base")?,
+        planned_pair.action,
+    );
+    pair.scores = planned_pair.scores.clone();
+    pair.confidence = planned_pair.confidence;
+    pair.pinned = planned_pair.pinned;
+    Ok(pair)
+}
+
+pub fn event_hash(event: &Event) -> String {
+    let content: Vec<char> = format!("{}\n{}", event.style, event.text).chars().collect();
+    format!("{:016x}", fnv1a(&content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::dialogue_lines;
+
+    #[test]
+    fn pinned_pairs_survive_inserted_lines() {
+        let dialogue_events =
+            dialogue_lines(&["Good morning.", "Did you sleep well?", "Not really."]);
+        let base_events = dialogue_lines(&["Morning.", "Slept well?", "Nope."]);
+        let indices: Vec<usize> = (0..3).collect();
+        let dialogue = ScriptSelection {
+            events: &dialogue_events,
+            indices: &indices,
+        };
+        let base = ScriptSelection {
+            events: &base_events,
+            indices: &indices,
+        };
+
+        let mut pair = AlignedPair::new(vec![2], vec![1], AlignmentAction::None);
+        pair.pinned = true;
+        let plan = AlignmentPlan::from_pairs(
+            &[
+                AlignedPair::new(vec![0], vec![0], AlignmentAction::None),
+                pair,
+            ],
+            &dialogue,
+            &base,
+        );
+
+        // A line was added at the start of the edited base script
+        let edited_base_events = dialogue_lines(&["Hey.", "Morning.", "Slept well?", "Nope."]);
+        let edited_indices: Vec<usize> = (0..4).collect();
+        let edited_base = ScriptSelection {
+            events: &edited_base_events,
+            indices: &edited_indices,
+        };
+
+        let (pinned_pairs, errors) = plan.pinned_pairs(&dialogue, &edited_base);
+        assert!(errors.is_empty());
+        assert_eq!(pinned_pairs.len(), 1);
+        assert_eq!(pinned_pairs[0].original_indices, vec![2]);
+        assert_eq!(pinned_pairs[0].modified_indices, vec![2]);
+
+        let pairs = plan.apply(&dialogue, &edited_base).unwrap();
+        assert_eq!(pairs.len(), 5);
+        assert_eq!(pairs[1].modified_indices, vec![1]);
+        assert_eq!(pairs[1].original_indices, vec![0]);
+    }
+}
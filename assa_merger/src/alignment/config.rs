@@ -0,0 +1,263 @@
+// Thresholds used by the aligner
+//
+// The values can be tuned per show from a TOML file, e.g.
+//
+// preset = "loose"
+// distance_match_threshold = 0.9
+// segment_tolerance_ms = 1000
+//
+// Every value that is left out falls back to the chosen preset.
+
+use std::{fs, path::Path, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("could not read config file")]
+    IoError(std::io::Error),
+    #[error("malformed config ({0})")]
+    FormatError(String),
+    #[error("unknown preset ({0}), expected strict, default or loose")]
+    UnknownPreset(String),
+}
+
+impl From<std::io::Error> for ConfigError {
+    fn from(error: std::io::Error) -> Self {
+        ConfigError::IoError(error)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    fn from(error: toml::de::Error) -> Self {
+        ConfigError::FormatError(error.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConfigPreset {
+    /// Only acts on clear matches, leaves more lines for manual review
+    Strict,
+    Default,
+    /// Accepts weaker matches, for files with heavily rewritten dialogue
+    Loose,
+}
+
+impl FromStr for ConfigPreset {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "strict" => Ok(ConfigPreset::Strict),
+            "default" => Ok(ConfigPreset::Default),
+            "loose" => Ok(ConfigPreset::Loose),
+            _ => Err(ConfigError::UnknownPreset(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlignmentConfig {
+    // Text distance stage (levenshtein ratio)
+    /// Lines at least this similar are accepted as a match right away
+    pub distance_match_threshold: f64,
+    /// Minimum similarity before a split or merge is considered
+    pub distance_action_threshold: f64,
+
+    // Semantic stage (cosine similarity of sentence embeddings)
+    /// Minimum similarity before the semantic stage takes any action
+    pub semantic_action_threshold: f64,
+    /// A line that matches both as split and as merge above this threshold
+    /// is a sentence that was split the same way in both files
+    pub kept_split_threshold: f64,
+    /// The next modified line has to be this many times more similar than
+    /// the best match to be treated as a line missing from the original
+    pub next_line_factor: f64,
+    /// Similarities closer than this to the current line count as equal
+    pub equal_similarity_margin: f64,
+    /// Above this similarity the current line is trusted over a split or
+    /// merge that is at most confident_current_margin better
+    pub confident_current_threshold: f64,
+    pub confident_current_margin: f64,
+    /// How far a split may fall behind the best similarity when one of its
+    /// parts is a better match than anything else
+    pub split_margin: f64,
+    /// How much better a merge has to be than the current line
+    pub merge_gain: f64,
+    /// How much each extra line has to improve a merge before it's included
+    pub merge_step_gain: f64,
+
+    // Sync detection
+    /// Minimum levenshtein ratio of an anchor line
+    pub anchor_min_similarity: f64,
+    /// Minimum semantic similarity of an anchor line in cross-lingual mode
+    pub anchor_min_semantic_similarity: f64,
+    /// Shorter lines are too ambiguous to be used as anchor
+    pub anchor_min_length: usize,
+    /// Offsets within this many milliseconds belong to the same segment
+    pub segment_tolerance_ms: i64,
+    /// Consecutive anchors needed before a new segment is started
+    pub segment_min_anchors: usize,
+
+    // Cross-lingual mode
+    /// Weight of the timing overlap versus the semantic similarity
+    pub cross_lingual_timing_weight: f64,
+
+    // Speaker hints
+    /// Factor applied to a split or merge that puts lines of more speakers
+    /// into one line than it has dash-prefixed turns for
+    pub speaker_change_penalty: f64,
+
+    // Overlapping events
+    /// Weight of the layer and \an position versus the content when matching
+    /// events shown at the same time
+    pub overlap_placement_weight: f64,
+
+    // Confidence of the aligned pairs
+    /// Weight of the timing overlap versus the text similarity
+    pub confidence_timing_weight: f64,
+    /// Pairs below this confidence are listed for review
+    pub review_threshold: f64,
+}
+
+impl Default for AlignmentConfig {
+    fn default() -> Self {
+        Self::preset(ConfigPreset::Default)
+    }
+}
+
+impl AlignmentConfig {
+    pub fn preset(preset: ConfigPreset) -> Self {
+        match preset {
+            ConfigPreset::Default => Self {
+                distance_match_threshold: 0.85,
+                distance_action_threshold: 0.6,
+                semantic_action_threshold: 0.3,
+                kept_split_threshold: 0.8,
+                next_line_factor: 1.3,
+                equal_similarity_margin: 0.005,
+                confident_current_threshold: 0.8,
+                confident_current_margin: 0.1,
+                split_margin: 0.2,
+                merge_gain: 0.1,
+                merge_step_gain: 0.1,
+                anchor_min_similarity: 0.9,
+                anchor_min_semantic_similarity: 0.85,
+                anchor_min_length: 8,
+                segment_tolerance_ms: 750,
+                segment_min_anchors: 3,
+                cross_lingual_timing_weight: 0.5,
+                speaker_change_penalty: 0.85,
+                overlap_placement_weight: 0.2,
+                confidence_timing_weight: 0.2,
+                review_threshold: 0.6,
+            },
+            ConfigPreset::Strict => Self {
+                distance_match_threshold: 0.9,
+                distance_action_threshold: 0.7,
+                semantic_action_threshold: 0.4,
+                kept_split_threshold: 0.85,
+                next_line_factor: 1.5,
+                equal_similarity_margin: 0.005,
+                confident_current_threshold: 0.85,
+                confident_current_margin: 0.05,
+                split_margin: 0.15,
+                merge_gain: 0.15,
+                merge_step_gain: 0.15,
+                anchor_min_similarity: 0.95,
+                anchor_min_semantic_similarity: 0.9,
+                anchor_min_length: 12,
+                segment_tolerance_ms: 500,
+                segment_min_anchors: 4,
+                cross_lingual_timing_weight: 0.5,
+                speaker_change_penalty: 0.8,
+                overlap_placement_weight: 0.2,
+                confidence_timing_weight: 0.2,
+                review_threshold: 0.7,
+            },
+            ConfigPreset::Loose => Self {
+                distance_match_threshold: 0.8,
+                distance_action_threshold: 0.5,
+                semantic_action_threshold: 0.25,
+                kept_split_threshold: 0.75,
+                next_line_factor: 1.2,
+                equal_similarity_margin: 0.01,
+                confident_current_threshold: 0.75,
+                confident_current_margin: 0.15,
+                split_margin: 0.25,
+                merge_gain: 0.05,
+                merge_step_gain: 0.05,
+                anchor_min_similarity: 0.85,
+                anchor_min_semantic_similarity: 0.8,
+                anchor_min_length: 6,
+                segment_tolerance_ms: 1000,
+                segment_min_anchors: 2,
+                cross_lingual_timing_weight: 0.6,
+                speaker_change_penalty: 0.9,
+                overlap_placement_weight: 0.2,
+                confidence_timing_weight: 0.2,
+                review_threshold: 0.5,
+            },
+        }
+    }
+
+    pub fn from_file(path: &Path, preset: ConfigPreset) -> Result<Self, ConfigError> {
+        Self::preset(preset).with_toml(&fs::read_to_string(path)?)
+    }
+
+    /// Overrides the values set in the TOML document. A `preset` key replaces
+    /// all values that are not set in the document itself.
+    pub fn with_toml(self, toml_string: &str) -> Result<Self, ConfigError> {
+        let mut overrides: toml::Table = toml::from_str(toml_string)?;
+
+        let mut config = match overrides.remove("preset") {
+            Some(toml::Value::String(preset)) => Self::preset(ConfigPreset::from_str(&preset)?),
+            Some(value) => return Err(ConfigError::UnknownPreset(value.to_string())),
+            None => self,
+        };
+
+        let mut table = toml::Table::try_from(&config)
+            .map_err(|error| ConfigError::FormatError(error.to_string()))?;
+        table.extend(overrides);
+        config = table.try_into()?;
+        Ok(config)
+    }
+
+    /// Applies a single `key=value` override, as given on the command line.
+    pub fn with_override(self, assignment: &str) -> Result<Self, ConfigError> {
+        match assignment.split_once('=') {
+            Some((key, value)) => self.with_toml(&format!("{} = {}", key.trim(), value.trim())),
+            None => Err(ConfigError::FormatError(format!(
+                "expected key=value, got {}",
+                assignment
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_toml_overrides_preset() {
+        let config = AlignmentConfig::default()
+            .with_toml("preset = \"strict\"\nmerge_gain = 0.2\nsegment_min_anchors = 5")
+            .unwrap();
+
+        assert_eq!(config.merge_gain, 0.2);
+        assert_eq!(config.segment_min_anchors, 5);
+        assert_eq!(
+            config.distance_match_threshold,
+            AlignmentConfig::preset(ConfigPreset::Strict).distance_match_threshold
+        );
+
+        let config = config.with_override("split_margin=0.3").unwrap();
+        assert_eq!(config.split_margin, 0.3);
+        assert!(AlignmentConfig::default()
+            .with_override("unknown_threshold=0.3")
+            .is_err());
+    }
+}
@@ -3,13 +3,14 @@ mod similarity;
 use std::cmp::min;
 
 use super::super::event_processor::get_text_of_events;
+use super::speaker::fits_speakers;
 use super::text_processor::levenshtein_ratio;
 use super::text_processor::split_count;
 use super::{AlignmentAction, ComparisonContext};
 use assa_parse::assa_file::event::Event;
 use similarity::{calc_merge_similarity, calc_split_similarity};
 
-pub fn text_distance_alignment(context: &mut ComparisonContext, self_call: bool) -> (bool, f64) {
+pub fn text_distance_alignment(context: &mut ComparisonContext) -> (bool, f64) {
     let (current_similarity, (split_similarity, _), (merge_similarity, merge_lines)) =
         get_similarities(
             *context.comparison_loop_index,
@@ -17,9 +18,10 @@ pub fn text_distance_alignment(context: &mut ComparisonContext, self_call: bool)
             context.lookahead,
             context.original_events,
             context.modified_events,
+            context.config.speaker_change_penalty,
         );
 
-    if current_similarity >= 0.85 {
+    if current_similarity >= context.config.distance_match_threshold {
         *context.comparison_loop_index += 1;
         *context.prev_alignment_action = AlignmentAction::None;
         return (true, current_similarity);
@@ -32,25 +34,17 @@ pub fn text_distance_alignment(context: &mut ComparisonContext, self_call: bool)
         .max_by(|a, b| a.partial_cmp(b).unwrap())
         .unwrap();
 
-    if *maximum_similarity >= 0.6 {
+    if *maximum_similarity >= context.config.distance_action_threshold {
         if *maximum_similarity == current_similarity {
             *context.prev_alignment_action = AlignmentAction::None;
             return (false, current_similarity);
         } else if *maximum_similarity == split_similarity {
-            if !self_call {
-                println!("distance: split");
-                println!("");
-            }
             *context.prev_offset = *context.offset;
             *context.offset += 1;
             *context.comparison_loop_index += 1;
             *context.prev_alignment_action = AlignmentAction::Split;
             return (true, split_similarity);
         } else if *maximum_similarity == merge_similarity {
-            if !self_call {
-                println!("distance: merged ({})\n", merge_lines.len());
-                println!();
-            }
             *context.prev_offset = *context.offset;
             *context.offset -= merge_lines.len() as i32;
             *context.comparison_loop_index += merge_lines.len() + 1;
@@ -68,6 +62,7 @@ fn get_similarities<'a>(
     lookahead: usize,
     original_events: &'a Vec<&'a Event>,
     modified_events: &'a Vec<&'a Event>,
+    speaker_change_penalty: f64,
 ) -> (f64, (f64, Vec<&'a String>), (f64, Vec<&'a String>)) {
     let original_event = original_events[index];
     let modified_event = modified_events[(index as i32 + offset) as usize];
@@ -76,20 +71,45 @@ fn get_similarities<'a>(
 
     let current_similarity = levenshtein_ratio(original_text, modified_text);
 
+    // Lines of different speakers only end up in one line with a dash for
+    // each of them, "- Hi\N- Hello"
+    let with_speakers = |similarity: f64, parts: &[&Event], combined: &Event| match fits_speakers(
+        parts, combined,
+    ) {
+        true => similarity,
+        false => similarity * speaker_change_penalty,
+    };
+
+    // Near the end of the scripts there may be fewer lines left than parts
+    let modified_index = (index as i32 + offset) as usize;
+    let split_end = min(
+        modified_index + min(split_count(&original_text), lookahead),
+        modified_events.len(),
+    );
     let (split_similarity, split_lines) = calc_split_similarity(
         &original_text,
-        &get_text_of_events(
-            &modified_events[(index as i32 + offset) as usize
-                ..(index as i32 + offset) as usize + min(split_count(&original_text), lookahead)],
-        ),
+        &get_text_of_events(&modified_events[modified_index..split_end]),
+    );
+    let split_similarity = with_speakers(
+        split_similarity,
+        &modified_events[modified_index..modified_index + split_lines.len()],
+        original_event,
     );
 
+    let merge_end = min(
+        index + min(split_count(&modified_text), lookahead),
+        original_events.len(),
+    );
     let (merge_similarity, merge_lines) = calc_merge_similarity(
-        &get_text_of_events(
-            &original_events[index..index + min(split_count(&modified_text), lookahead)],
-        ),
+        &get_text_of_events(&original_events[index..merge_end]),
         &modified_text,
     );
+    // merge_lines doesn't count the first line
+    let merge_similarity = with_speakers(
+        merge_similarity,
+        &original_events[index..min(index + merge_lines.len() + 1, original_events.len())],
+        modified_event,
+    );
 
     (
         current_similarity,
@@ -97,3 +117,31 @@ fn get_similarities<'a>(
         (merge_similarity, merge_lines),
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::dialogue;
+
+    fn event(actor: &str, text: &str) -> Event {
+        Event {
+            name: actor.to_string(),
+            ..dialogue(1.0, 2.0, text)
+        }
+    }
+
+    #[test]
+    fn get_similarities_penalizes_merged_speakers() {
+        let original = [event("Anna", "Are you coming?"), event("Ben", "Not today.")];
+        let original: Vec<&Event> = original.iter().collect();
+        let merge_similarity = |modified_text: &str| {
+            let modified = [event("", modified_text), event("", "Why not?")];
+            let modified: Vec<&Event> = modified.iter().collect();
+            get_similarities(0, 0, 4, &original, &modified, 0.5).2 .0
+        };
+
+        let without_turns = merge_similarity("Are you coming? Not today.");
+        let with_turns = merge_similarity(r"- Are you coming?\N- Not today.");
+        assert!(without_turns < with_turns * 0.75);
+    }
+}
@@ -18,7 +18,7 @@ pub fn calc_split_similarity<'a>(line: &str, splits: &Vec<&'a String>) -> (f64,
         let mean_levenshtein_ratio = total_levenshtein_ratio / split_count as f64;
         if mean_levenshtein_ratio > result.0 {
             result.0 = mean_levenshtein_ratio;
-            result.1 = splits[0..potential_split.len()].to_vec();
+            result.1 = splits[0..min(split_count, splits.len())].to_vec();
         }
     }
 
@@ -0,0 +1,239 @@
+// Evaluation of the aligner against a gold alignment
+//
+// The gold alignment is an alignment plan that was checked by hand, e.g. saved
+// with --save-plan and corrected in the review UI. A predicted pair is correct
+// when the gold alignment has a pair with exactly the same lines on both sides,
+// precision and recall are counted per operation so the effect of a threshold
+// change on e.g. splits can be measured on its own.
+
+use std::collections::HashSet;
+use std::fmt::{self, Display, Write};
+
+use super::aligned_pair::AlignedPair;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    OneToOne,
+    /// One dialogue line over several base lines
+    Split,
+    /// Several dialogue lines in one base line, or several in several
+    Merge,
+    /// A base line without dialogue
+    Insertion,
+    /// A dialogue line without a base line
+    Deletion,
+}
+
+impl Operation {
+    pub const ALL: [Operation; 5] = [
+        Operation::OneToOne,
+        Operation::Split,
+        Operation::Merge,
+        Operation::Insertion,
+        Operation::Deletion,
+    ];
+
+    pub fn of(original_count: usize, modified_count: usize) -> Self {
+        match (original_count, modified_count) {
+            (0, _) => Operation::Insertion,
+            (_, 0) => Operation::Deletion,
+            (1, 1) => Operation::OneToOne,
+            (1, _) => Operation::Split,
+            _ => Operation::Merge,
+        }
+    }
+}
+
+impl Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Operation::OneToOne => "1:1",
+            Operation::Split => "split",
+            Operation::Merge => "merge",
+            Operation::Insertion => "insertion",
+            Operation::Deletion => "deletion",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OperationScore {
+    pub predicted: usize,
+    pub gold: usize,
+    pub correct: usize,
+}
+
+impl OperationScore {
+    pub fn precision(&self) -> f64 {
+        ratio(self.correct, self.predicted)
+    }
+
+    pub fn recall(&self) -> f64 {
+        ratio(self.correct, self.gold)
+    }
+
+    pub fn f1(&self) -> f64 {
+        let (precision, recall) = (self.precision(), self.recall());
+        match precision + recall {
+            sum if sum > 0f64 => 2f64 * precision * recall / sum,
+            _ => 0f64,
+        }
+    }
+
+    fn add(&mut self, other: &OperationScore) {
+        self.predicted += other.predicted;
+        self.gold += other.gold;
+        self.correct += other.correct;
+    }
+}
+
+fn ratio(count: usize, total: usize) -> f64 {
+    match total {
+        0 => 0f64,
+        total => count as f64 / total as f64,
+    }
+}
+
+/// Scores per operation, in the order of `Operation::ALL`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Evaluation {
+    pub scores: [OperationScore; 5],
+}
+
+type PairKey = (Vec<usize>, Vec<usize>);
+
+// Lines without a partner are compared one by one, how the aligner groups
+// them doesn't matter
+fn pair_keys(pairs: &[AlignedPair]) -> Vec<PairKey> {
+    let mut keys: Vec<PairKey> = Vec::new();
+    for pair in pairs {
+        match pair.is_matched() {
+            true => {
+                let mut original_indices = pair.original_indices.clone();
+                let mut modified_indices = pair.modified_indices.clone();
+                original_indices.sort_unstable();
+                modified_indices.sort_unstable();
+                keys.push((original_indices, modified_indices));
+            }
+            false => {
+                keys.extend(pair.original_indices.iter().map(|&i| (vec![i], vec![])));
+                keys.extend(pair.modified_indices.iter().map(|&i| (vec![], vec![i])));
+            }
+        }
+    }
+    keys
+}
+
+fn operation_index(key: &PairKey) -> usize {
+    let operation = Operation::of(key.0.len(), key.1.len());
+    Operation::ALL
+        .iter()
+        .position(|&other| other == operation)
+        .unwrap()
+}
+
+impl Evaluation {
+    pub fn new(predicted: &[AlignedPair], gold: &[AlignedPair]) -> Self {
+        let predicted_keys = pair_keys(predicted);
+        let gold_keys: HashSet<PairKey> = pair_keys(gold).into_iter().collect();
+
+        let mut evaluation = Evaluation::default();
+        for key in &predicted_keys {
+            let score = &mut evaluation.scores[operation_index(key)];
+            score.predicted += 1;
+            if gold_keys.contains(key) {
+                score.correct += 1;
+            }
+        }
+        for key in &gold_keys {
+            evaluation.scores[operation_index(key)].gold += 1;
+        }
+        evaluation
+    }
+
+    pub fn score(&self, operation: Operation) -> &OperationScore {
+        let index = Operation::ALL
+            .iter()
+            .position(|&other| other == operation)
+            .unwrap();
+        &self.scores[index]
+    }
+
+    /// Counts of all operations together
+    pub fn total(&self) -> OperationScore {
+        let mut total = OperationScore::default();
+        self.scores.iter().for_each(|score| total.add(score));
+        total
+    }
+
+    /// Adds the counts of another episode
+    pub fn add(&mut self, other: &Evaluation) {
+        for (score, other_score) in self.scores.iter_mut().zip(&other.scores) {
+            score.add(other_score);
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut report = format!(
+            "{:<9}  {:>9}  {:>4}  {:>7}  {:>9}  {:>6}  {:>5}\n",
+            "Operation", "Predicted", "Gold", "Correct", "Precision", "Recall", "F1"
+        );
+        let rows = Operation::ALL
+            .iter()
+            .map(|operation| (operation.to_string(), *self.score(*operation)))
+            .chain([(String::from("all"), self.total())]);
+        for (name, score) in rows {
+            let _ = writeln!(
+                report,
+                "{:<9}  {:>9}  {:>4}  {:>7}  {:>9.3}  {:>6.3}  {:>5.3}",
+                name,
+                score.predicted,
+                score.gold,
+                score.correct,
+                score.precision(),
+                score.recall(),
+                score.f1()
+            );
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::pair;
+
+    #[test]
+    fn pairs_are_scored_per_operation() {
+        let gold = [
+            pair(vec![0], vec![0], 0.0),
+            pair(vec![1], vec![1, 2], 0.0),
+            pair(vec![2], vec![3], 0.0),
+            pair(vec![], vec![4], 0.0),
+            pair(vec![3], vec![], 0.0),
+        ];
+        let predicted = [
+            pair(vec![0], vec![0], 0.0),
+            pair(vec![1], vec![1], 0.0),
+            pair(vec![2], vec![2, 3], 0.0),
+            pair(vec![], vec![4], 0.0),
+            pair(vec![3], vec![], 0.0),
+        ];
+
+        let evaluation = Evaluation::new(&predicted, &gold);
+        let one_to_one = evaluation.score(Operation::OneToOne);
+        assert_eq!(
+            (one_to_one.predicted, one_to_one.gold, one_to_one.correct),
+            (2, 2, 1)
+        );
+        assert_eq!(evaluation.score(Operation::Split).recall(), 0f64);
+        assert_eq!(evaluation.score(Operation::Insertion).f1(), 1f64);
+        assert_eq!(evaluation.total().precision(), 0.6);
+
+        let mut season = evaluation.clone();
+        season.add(&evaluation);
+        assert_eq!(season.total().correct, 6);
+    }
+}
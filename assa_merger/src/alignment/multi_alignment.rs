@@ -0,0 +1,114 @@
+// Alignment of several dialogue sources with the same base
+//
+// Every source is aligned with the base on its own, the pairs are then indexed
+// by base line so the translations of a line can be compared and one of them
+// picked per line.
+
+use super::aligned_pair::AlignedPair;
+
+pub struct MultiAlignment {
+    /// Aligned pairs per source, in order of priority
+    pub sources: Vec<Vec<AlignedPair>>,
+    /// Per base line and source, the position of the matched pair of the
+    /// source that contains the line
+    rows: Vec<Vec<Option<usize>>>,
+}
+
+impl MultiAlignment {
+    pub fn new(base_count: usize, sources: Vec<Vec<AlignedPair>>) -> Self {
+        let mut rows = vec![vec![None; sources.len()]; base_count];
+        for (source, pairs) in sources.iter().enumerate() {
+            for (position, pair) in pairs.iter().enumerate() {
+                if !pair.is_matched() {
+                    continue;
+                }
+                for &base_index in &pair.modified_indices {
+                    rows[base_index][source] = Some(position);
+                }
+            }
+        }
+        Self { sources, rows }
+    }
+
+    pub fn base_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The matched pair of the source that contains the base line
+    pub fn pair(&self, base_index: usize, source: usize) -> Option<&AlignedPair> {
+        self.rows[base_index][source].map(|position| &self.sources[source][position])
+    }
+
+    /// Picks a source for every base line. The first source with a match of
+    /// at least `min_confidence` wins, or the first source with any match when
+    /// none is that confident. A pair that covers several base lines is
+    /// picked for all of them.
+    pub fn vote(&self, min_confidence: f64) -> Vec<Option<usize>> {
+        let mut chosen: Vec<Option<usize>> = vec![None; self.base_count()];
+        for base_index in 0..self.base_count() {
+            if chosen[base_index].is_some() {
+                continue;
+            }
+            let candidates: Vec<(usize, &AlignedPair)> = (0..self.sources.len())
+                .filter_map(|source| self.pair(base_index, source).map(|pair| (source, pair)))
+                .filter(|(_, pair)| pair.modified_indices.iter().all(|&i| chosen[i].is_none()))
+                .collect();
+            let pick = candidates
+                .iter()
+                .find(|(_, pair)| pair.confidence >= min_confidence)
+                .or(candidates.first());
+            if let Some(&(source, pair)) = pick {
+                for &i in &pair.modified_indices {
+                    chosen[i] = Some(source);
+                }
+            }
+        }
+        chosen
+    }
+
+    /// The pairs of the source that won the vote
+    pub fn chosen_pairs(&self, chosen: &[Option<usize>], source: usize) -> Vec<AlignedPair> {
+        self.sources[source]
+            .iter()
+            .filter(|pair| {
+                pair.is_matched()
+                    && pair
+                        .modified_indices
+                        .iter()
+                        .all(|&i| chosen[i] == Some(source))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::pair;
+
+    #[test]
+    fn vote_prefers_confident_matches_by_priority() {
+        let alignment = MultiAlignment::new(
+            4,
+            vec![
+                vec![
+                    pair(vec![0], vec![0], 0.9),
+                    pair(vec![1], vec![1], 0.3),
+                    pair(vec![], vec![2], 0.0),
+                    pair(vec![2], vec![3], 0.4),
+                ],
+                vec![
+                    pair(vec![0], vec![0], 0.95),
+                    pair(vec![1], vec![1, 2], 0.8),
+                    pair(vec![2], vec![3], 0.2),
+                ],
+            ],
+        );
+
+        let chosen = alignment.vote(0.6);
+        assert_eq!(chosen, vec![Some(0), Some(1), Some(1), Some(0)]);
+        assert_eq!(alignment.chosen_pairs(&chosen, 1).len(), 1);
+        assert_eq!(alignment.pair(2, 0).map(|pair| pair.confidence), None);
+    }
+}
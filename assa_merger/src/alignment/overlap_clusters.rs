@@ -0,0 +1,195 @@
+// Puts overlapping events of the modified file in the order of the original
+//
+// Events shown at the same time (two speakers talking over each other, top
+// and bottom lines, background chatter on another layer) are sorted by start
+// time, which puts them in a different order in every file. The aligner walks
+// both files in order, so the modified events of each cluster of overlapping
+// events are reordered to follow the original events they match on content,
+// layer and position.
+
+use std::cmp::max;
+use std::ops::Range;
+
+use assa_parse::assa_file::{event::Event, override_tags::TextSegment};
+
+use super::config::AlignmentConfig;
+use super::semantic_alignment::semantic_similarity::SimilarityBackend;
+use super::sync_detection::OffsetMap;
+use super::text_processor::prep_for_value_measuring;
+
+/// Ranges of events that overlap in time, the events have to be sorted by
+/// start time. Only ranges of more than one event are returned.
+pub fn overlap_clusters(events: &[&Event]) -> Vec<Range<usize>> {
+    let mut clusters: Vec<Range<usize>> = Vec::new();
+    let mut cluster_start = 0;
+    let mut cluster_end = None;
+
+    for (i, event) in events.iter().enumerate() {
+        match cluster_end {
+            Some(end) if event.start < end => cluster_end = Some(max(end, event.end)),
+            _ => {
+                if i - cluster_start > 1 {
+                    clusters.push(cluster_start..i);
+                }
+                cluster_start = i;
+                cluster_end = Some(event.end);
+            }
+        }
+    }
+    if events.len() - cluster_start > 1 {
+        clusters.push(cluster_start..events.len());
+    }
+    clusters
+}
+
+/// Order in which the modified events are aligned, position i holds the index
+/// of a modified event. Events outside of clusters keep their position.
+pub fn cluster_order(
+    original_events: &[&Event],
+    modified_events: &[&Event],
+    offset_map: &OffsetMap,
+    semantic_similarity: &dyn SimilarityBackend,
+    config: &AlignmentConfig,
+) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..modified_events.len()).collect();
+
+    for cluster in overlap_clusters(modified_events) {
+        let cluster_start = modified_events[cluster.start].start;
+        let cluster_end = modified_events[cluster.clone()]
+            .iter()
+            .map(|event| event.end)
+            .max()
+            .unwrap();
+        let originals: Vec<usize> = (0..original_events.len())
+            .filter(|&i| {
+                let offset = offset_map.offset_at(original_events[i].start);
+                original_events[i].start + offset < cluster_end
+                    && original_events[i].end + offset > cluster_start
+            })
+            .collect();
+        if originals.len() < 2 {
+            continue;
+        }
+
+        let texts: Vec<String> = originals
+            .iter()
+            .map(|&i| original_events[i])
+            .chain(cluster.clone().map(|i| modified_events[i]))
+            .map(|event| prep_for_value_measuring(&event.text))
+            .collect();
+        let vectors = semantic_similarity.encode(&texts);
+        let (original_vectors, modified_vectors) = vectors.split_at(originals.len());
+
+        let mut candidates: Vec<(f64, usize, usize)> = Vec::new();
+        for (o, &original_index) in originals.iter().enumerate() {
+            for (m, modified_index) in cluster.clone().enumerate() {
+                let content =
+                    semantic_similarity.cosine_distance(&original_vectors[o], &modified_vectors[m]);
+                if content < config.semantic_action_threshold {
+                    continue;
+                }
+                let placement = placement_similarity(
+                    original_events[original_index],
+                    modified_events[modified_index],
+                );
+                candidates.push((
+                    (1f64 - config.overlap_placement_weight) * content
+                        + config.overlap_placement_weight * placement,
+                    original_index,
+                    modified_index,
+                ));
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        // Best matches first, every event is matched once
+        let mut matches: Vec<(usize, usize)> = Vec::new();
+        for (_, original_index, modified_index) in candidates {
+            if !matches
+                .iter()
+                .any(|&(o, m)| o == original_index || m == modified_index)
+            {
+                matches.push((original_index, modified_index));
+            }
+        }
+
+        // The matched events swap places among themselves, so the unmatched
+        // ones stay where they are
+        let mut slots: Vec<usize> = matches.iter().map(|&(_, m)| m).collect();
+        slots.sort();
+        matches.sort();
+        for (slot, (_, modified_index)) in slots.into_iter().zip(matches) {
+            order[slot] = modified_index;
+        }
+    }
+    order
+}
+
+/// 1 when both events are on the same layer and in the same spot on screen,
+/// 0.5 when one of them matches and 0 otherwise
+fn placement_similarity(original_event: &Event, modified_event: &Event) -> f64 {
+    let same_layer = original_event.layer == modified_event.layer;
+    let same_position = vertical_position(original_event) == vertical_position(modified_event);
+    (same_layer as u8 + same_position as u8) as f64 / 2f64
+}
+
+/// Row of the \an tag of the event (0 bottom, 1 middle, 2 top), None when the
+/// event is placed by its style
+fn vertical_position(event: &Event) -> Option<u8> {
+    event
+        .text_segments()
+        .into_iter()
+        .filter_map(|segment| match segment {
+            TextSegment::Tags(tags) => Some(tags),
+            _ => None,
+        })
+        .flatten()
+        .filter(|tag| tag.name == "an")
+        .find_map(|tag| tag.value.parse::<u8>().ok())
+        .filter(|alignment| (1..=9).contains(alignment))
+        .map(|alignment| (alignment - 1) / 3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::semantic_alignment::lexical_similarity::LexicalSimilarity;
+    use super::*;
+    use crate::test_helpers::dialogue;
+
+    fn event(layer: u8, start: u8, end: u8, text: &str) -> Event {
+        Event {
+            layer,
+            ..dialogue(f64::from(start), f64::from(end), text)
+        }
+    }
+
+    #[test]
+    fn cluster_order_follows_original() {
+        let original = [
+            event(0, 1, 3, "Good morning."),
+            event(0, 5, 9, "Where did you put my keys?"),
+            event(1, 5, 8, r"{\an8}Not again..."),
+            event(0, 6, 9, "Check the kitchen table."),
+            event(0, 12, 14, "Thanks."),
+        ];
+        let modified = [
+            event(0, 1, 3, "Good morning."),
+            event(1, 5, 8, r"{\an8}Not again..."),
+            event(0, 5, 9, "Where did you leave my keys?"),
+            event(0, 6, 9, "Look on the kitchen table."),
+            event(0, 12, 14, "Thanks."),
+        ];
+        let original: Vec<&Event> = original.iter().collect();
+        let modified: Vec<&Event> = modified.iter().collect();
+
+        assert_eq!(overlap_clusters(&modified), vec![1..4]);
+        let order = cluster_order(
+            &original,
+            &modified,
+            &OffsetMap { segments: vec![] },
+            &LexicalSimilarity::default(),
+            &AlignmentConfig::default(),
+        );
+        assert_eq!(order, vec![0, 2, 1, 3, 4]);
+    }
+}
@@ -1,18 +1,22 @@
+pub mod embedding_cache;
+pub mod lexical_similarity;
 pub mod semantic_similarity;
 mod similarity;
 
 use std::cmp::min;
 
 use super::super::event_processor::get_text_of_events;
+use super::speaker::fits_speakers;
 use super::text_processor::{prep_for_value_measuring, split_count};
-use super::{AlignmentAction, ComparisonContext};
+use super::timing::timing_similarity;
+use super::{AlignmentAction, AlignmentMode, ComparisonContext};
 use assa_parse::assa_file::event::Event;
-use semantic_similarity::SemanticSimilarity;
+use semantic_similarity::SimilarityBackend;
 use similarity::{calc_merge_similarity, calc_split_similarity};
 
 pub fn semantic_alignment(
     context: &mut ComparisonContext,
-    semantic_similarity: &SemanticSimilarity,
+    semantic_similarity: &dyn SimilarityBackend,
 ) -> (bool, f64) {
     let prev_action_was_a_split = match context.prev_alignment_action {
         AlignmentAction::Split => true,
@@ -27,16 +31,7 @@ pub fn semantic_alignment(
         prev_split_merge_similarity,
         prev_similarity,
         next_similarity,
-    ) = get_similarities(
-        *context.comparison_loop_index,
-        *context.offset,
-        *context.prev_offset,
-        context.lookahead,
-        context.original_events,
-        context.modified_events,
-        context.prev_alignment_action,
-        semantic_similarity,
-    );
+    ) = get_similarities(context, semantic_similarity);
 
     let similarities: Vec<f64> = vec![current_similarity, split_similarity, merge_similarity];
 
@@ -55,33 +50,15 @@ pub fn semantic_alignment(
         false => 0f64,
     };
 
-    let do_println = context.original_events[*context.comparison_loop_index].text
-        == r#"Running right through the minefield? Is he an idiot?"#;
-
-    if do_println {
-        println!(
-            "similarities: {} || {} || {} || {} || {} || {} || {}",
-            current_similarity,
-            split_similarity,
-            merge_similarity,
-            prev_similarity,
-            next_similarity,
-            prev_split_similarity,
-            prev_split_merge_similarity
-        );
-        println!("split similarities: {:?}", split_similarities);
-    }
-
-    if maximum_similarity >= 0.3 {
-        if merge_similarity >= 0.8 && split_similarity >= 0.8 {
-            println!("semantic: split sentence kept as split");
-            println!("");
+    let config = context.config;
+    if maximum_similarity >= config.semantic_action_threshold {
+        if merge_similarity >= config.kept_split_threshold
+            && split_similarity >= config.kept_split_threshold
+        {
             *context.comparison_loop_index += 2;
             *context.prev_alignment_action = AlignmentAction::None;
             return (true, (merge_similarity + split_similarity) / 2f64);
-        } else if next_similarity > maximum_similarity * 1.3 {
-            println!("semantic: modified addition");
-            println!("");
+        } else if next_similarity > maximum_similarity * config.next_line_factor {
             *context.prev_offset = *context.offset;
             *context.offset += 1;
             // *context.comparison_loop_index += 1;
@@ -91,18 +68,14 @@ pub fn semantic_alignment(
             && prev_action_was_a_split
             && prev_split_merge_similarity > prev_split_similarity
         {
-            println!("semantic: prev detected");
-            println!("");
             *context.offset -= 1;
             *context.comparison_loop_index += 1;
             *context.prev_alignment_action = AlignmentAction::Prev;
             return (true, prev_similarity);
         } else if split_similarities.len() > 1
             && split_max_similarity > maximum_similarity
-            && split_similarity > maximum_similarity - 0.2
+            && split_similarity > maximum_similarity - config.split_margin
         {
-            println!("semantic: split");
-            println!("");
             *context.prev_offset = *context.offset;
             *context.offset += split_lines.len() as i32 - 1;
             *context.comparison_loop_index += 1;
@@ -111,26 +84,20 @@ pub fn semantic_alignment(
         }
 
         if maximum_similarity == current_similarity
-            || maximum_similarity - current_similarity <= 0.005
-            || (current_similarity >= 0.8 && maximum_similarity - current_similarity <= 0.1)
+            || maximum_similarity - current_similarity <= config.equal_similarity_margin
+            || (current_similarity >= config.confident_current_threshold
+                && maximum_similarity - current_similarity <= config.confident_current_margin)
         {
             return (false, current_similarity);
         } else if maximum_similarity == split_similarity {
-            println!("semantic: split");
-            println!("");
             *context.prev_offset = *context.offset;
             *context.offset += split_lines.len() as i32 - 1;
             *context.comparison_loop_index += 1;
             *context.prev_alignment_action = AlignmentAction::Split;
             return (true, split_similarity);
         } else if maximum_similarity == merge_similarity
-            && merge_similarity - current_similarity >= 0.1
+            && merge_similarity - current_similarity >= config.merge_gain
         {
-            println!("semantic: merged");
-            println!("");
-            if do_println {
-                println!("lines: {:?}", merge_lines);
-            }
             *context.prev_offset = *context.offset;
             *context.offset -= merge_lines.len() as i32 - 1;
             *context.comparison_loop_index += merge_lines.len();
@@ -139,20 +106,16 @@ pub fn semantic_alignment(
         }
     }
 
-    if prev_similarity > 0.3 && prev_similarity > next_similarity {
+    if prev_similarity > config.semantic_action_threshold && prev_similarity > next_similarity {
         if let AlignmentAction::Split = context.prev_alignment_action {
             if prev_split_merge_similarity > prev_split_similarity {
-                println!("semantic: prev detected");
-                println!("");
                 *context.offset -= 1;
                 *context.comparison_loop_index += 1;
                 *context.prev_alignment_action = AlignmentAction::Prev;
                 return (true, prev_similarity);
             }
         }
-    } else if next_similarity > 0.3 {
-        println!("semantic: modified addition");
-        println!("");
+    } else if next_similarity > config.semantic_action_threshold {
         *context.prev_offset = *context.offset;
         *context.offset += 1;
         // *context.comparison_loop_index += 1;
@@ -164,14 +127,8 @@ pub fn semantic_alignment(
 }
 
 fn get_similarities<'a>(
-    index: usize,
-    offset: i32,
-    prev_offset: i32,
-    lookahead: usize,
-    original_events: &'a Vec<&'a Event>,
-    modified_events: &'a Vec<&'a Event>,
-    prev_alignment_action: &'a mut AlignmentAction,
-    semantic_similarity: &SemanticSimilarity,
+    context: &ComparisonContext<'a>,
+    semantic_similarity: &dyn SimilarityBackend,
 ) -> (
     f64,
     (f64, Vec<f64>, Vec<String>),
@@ -181,31 +138,97 @@ fn get_similarities<'a>(
     f64,
     f64,
 ) {
+    let index = *context.comparison_loop_index;
+    let offset = *context.offset;
+    let prev_offset = *context.prev_offset;
+    let lookahead = context.lookahead;
+    let original_events: &'a Vec<&'a Event> = context.original_events;
+    let modified_events: &'a Vec<&'a Event> = context.modified_events;
+    let prev_alignment_action = &*context.prev_alignment_action;
+
     let original_event = original_events[index];
-    let modified_event = modified_events[(index as i32 + offset) as usize];
+    let modified_index = (index as i32 + offset) as usize;
+    let modified_event = modified_events[modified_index];
     let original_text = &original_event.text;
     let modified_text = &modified_event.text;
 
-    let current_similarity = semantic_similarity.compare(
-        &prep_for_value_measuring(original_text),
-        &prep_for_value_measuring(modified_text),
+    // Across languages the meaning alone is too vague to pick between
+    // neighbouring lines, so it's combined with how well the timings overlap.
+    let time_offset = context.offset_map.offset_at(original_event.start);
+    let with_timing =
+        |similarity: f64, original_range: (usize, usize), modified_range: (usize, usize)| {
+            match context.mode {
+                AlignmentMode::Monolingual => similarity,
+                AlignmentMode::CrossLingual => {
+                    let originals = &original_events
+                        [original_range.0..min(original_range.1, original_events.len())];
+                    let modifieds = &modified_events
+                        [modified_range.0..min(modified_range.1, modified_events.len())];
+                    (1f64 - context.config.cross_lingual_timing_weight) * similarity
+                        + context.config.cross_lingual_timing_weight
+                            * timing_similarity(originals, modifieds, time_offset)
+                }
+            }
+        };
+
+    // Lines of different speakers only end up in one line with a dash for
+    // each of them, "- Hi\N- Hello"
+    let with_speakers = |similarity: f64, parts: &[&Event], combined: &Event| match fits_speakers(
+        parts, combined,
+    ) {
+        true => similarity,
+        false => similarity * context.config.speaker_change_penalty,
+    };
+
+    let current_similarity = with_timing(
+        semantic_similarity.compare(
+            &prep_for_value_measuring(original_text),
+            &prep_for_value_measuring(modified_text),
+        ),
+        (index, index + 1),
+        (modified_index, modified_index + 1),
     );
 
     let (split_similarity, split_similarities, split_lines) = calc_split_similarity(
         &original_text,
         &get_text_of_events(
-            &modified_events[(index as i32 + offset) as usize
-                ..(index as i32 + offset) as usize + min(split_count(&original_text), lookahead)],
+            &modified_events[modified_index
+                ..min(
+                    modified_index + min(split_count(&original_text), lookahead),
+                    modified_events.len(),
+                )],
         ),
         semantic_similarity,
     );
+    let split_similarity = with_timing(
+        split_similarity,
+        (index, index + 1),
+        (modified_index, modified_index + split_lines.len()),
+    );
+    let split_similarity = with_speakers(
+        split_similarity,
+        &modified_events
+            [modified_index..min(modified_index + split_lines.len(), modified_events.len())],
+        original_event,
+    );
 
     let mut minimal_merge_potential = 1;
-    let single_merge_len = original_text.len() + original_events[index + 1].text.len() + 1;
-    if modified_text.len().abs_diff(single_merge_len)
-        < modified_text.len().abs_diff(original_text.len())
-    {
-        minimal_merge_potential = 2;
+    match context.mode {
+        AlignmentMode::Monolingual => {
+            let single_merge_len = original_text.len() + original_events[index + 1].text.len() + 1;
+            if modified_text.len().abs_diff(single_merge_len)
+                < modified_text.len().abs_diff(original_text.len())
+            {
+                minimal_merge_potential = 2;
+            }
+        }
+        // Text lengths can't be compared across languages, a merge is likely
+        // when the next original line still starts within the modified line.
+        AlignmentMode::CrossLingual => {
+            if original_events[index + 1].start + time_offset < modified_event.end {
+                minimal_merge_potential = 2;
+            }
+        }
     }
     let mut original_split_count = split_count(&original_text);
     let modified_split_count = split_count(&modified_text);
@@ -226,33 +249,61 @@ fn get_similarities<'a>(
 
     let potential_merge_count = min(max_merge_count, lookahead);
     let (merge_similarity, merge_lines) = calc_merge_similarity(
-        &get_text_of_events(&original_events[index..index + potential_merge_count]),
+        &get_text_of_events(
+            &original_events[index..min(index + potential_merge_count, original_events.len())],
+        ),
         &modified_text,
+        context.config.merge_step_gain,
         semantic_similarity,
     );
+    let merge_similarity = with_timing(
+        merge_similarity,
+        (index, index + merge_lines.len()),
+        (modified_index, modified_index + 1),
+    );
+    let merge_similarity = with_speakers(
+        merge_similarity,
+        &original_events[index..min(index + merge_lines.len(), original_events.len())],
+        modified_event,
+    );
 
     let prev_similarity = match index as i32 + offset {
         0 => 0f64,
-        _ => semantic_similarity.compare(
-            &prep_for_value_measuring(original_text),
-            &prep_for_value_measuring(&modified_events[(index as i32 + offset - 1) as usize].text),
+        _ => with_timing(
+            semantic_similarity.compare(
+                &prep_for_value_measuring(original_text),
+                &prep_for_value_measuring(&modified_events[modified_index - 1].text),
+            ),
+            (index, index + 1),
+            (modified_index - 1, modified_index),
         ),
     };
 
-    let next_similarity = semantic_similarity.compare(
-        &prep_for_value_measuring(original_text),
-        &prep_for_value_measuring(&modified_events[(index as i32 + offset + 1) as usize].text),
-    );
+    let next_similarity = match modified_events.get(modified_index + 1) {
+        Some(next_event) => with_timing(
+            semantic_similarity.compare(
+                &prep_for_value_measuring(original_text),
+                &prep_for_value_measuring(&next_event.text),
+            ),
+            (index, index + 1),
+            (modified_index + 1, modified_index + 2),
+        ),
+        None => 0f64,
+    };
 
     let mut prev_split_similarity = 0f64;
     let mut prev_split_merge_similarity = 0f64;
     if let AlignmentAction::Split = prev_alignment_action {
+        let prev_modified_index = (index as i32 - 1 + prev_offset) as usize;
         let prev_split_lines = calc_split_similarity(
             &original_events[index - 1].text,
             &get_text_of_events(
-                &modified_events[(index as i32 - 1 + prev_offset) as usize
-                    ..(index as i32 - 1 + prev_offset) as usize
-                        + min(split_count(&original_events[index - 1].text), lookahead)],
+                &modified_events[prev_modified_index
+                    ..min(
+                        prev_modified_index
+                            + min(split_count(&original_events[index - 1].text), lookahead),
+                        modified_events.len(),
+                    )],
             ),
             semantic_similarity,
         )
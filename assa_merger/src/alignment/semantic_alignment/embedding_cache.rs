@@ -0,0 +1,171 @@
+// Memoising wrapper around a similarity backend
+//
+// The aligner compares the same lines many times (current, previous, next,
+// split and merge candidates), encoding every text only once removes most of
+// the time spent in the language model. Embeddings can be stored on disk and
+// reused when the same episode is aligned again.
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use super::semantic_similarity::SimilarityBackend;
+use crate::alignment::text_processor::prep_for_value_measuring;
+
+#[derive(Error, Debug)]
+pub enum EmbeddingCacheError {
+    #[error("could not access embedding cache file")]
+    IoError(std::io::Error),
+    #[error("malformed embedding cache file")]
+    FormatError(serde_json::Error),
+}
+
+impl From<std::io::Error> for EmbeddingCacheError {
+    fn from(error: std::io::Error) -> Self {
+        EmbeddingCacheError::IoError(error)
+    }
+}
+
+impl From<serde_json::Error> for EmbeddingCacheError {
+    fn from(error: serde_json::Error) -> Self {
+        EmbeddingCacheError::FormatError(error)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct EmbeddingCacheFile {
+    backend: String,
+    embeddings: HashMap<String, Vec<f32>>,
+}
+
+pub struct EmbeddingCache<'a> {
+    backend: &'a dyn SimilarityBackend,
+    /// Embeddings keyed by the text as returned by prep_for_value_measuring
+    embeddings: RefCell<HashMap<String, Vec<f32>>>,
+}
+
+impl<'a> EmbeddingCache<'a> {
+    pub fn new(backend: &'a dyn SimilarityBackend) -> Self {
+        Self {
+            backend,
+            embeddings: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Encodes all texts that are not cached yet in a single batch.
+    pub fn preload(&self, text_list: &[impl AsRef<str>]) {
+        let mut missing: Vec<String> = Vec::new();
+        {
+            let embeddings = self.embeddings.borrow();
+            for text in text_list {
+                let key = prep_for_value_measuring(text);
+                if !embeddings.contains_key(&key) && !missing.contains(&key) {
+                    missing.push(key);
+                }
+            }
+        }
+        if missing.is_empty() {
+            return;
+        }
+
+        let vectors = self.backend.encode(&missing);
+        self.embeddings
+            .borrow_mut()
+            .extend(missing.into_iter().zip(vectors));
+    }
+
+    pub fn len(&self) -> usize {
+        self.embeddings.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.embeddings.borrow().is_empty()
+    }
+
+    /// Loads previously stored embeddings. Files written with a different
+    /// backend are ignored, their vectors can't be compared with ours.
+    pub fn load_file(&self, path: &Path) -> Result<bool, EmbeddingCacheError> {
+        let cache_file: EmbeddingCacheFile =
+            serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        if cache_file.backend != self.backend.identifier() {
+            return Ok(false);
+        }
+
+        self.embeddings.borrow_mut().extend(cache_file.embeddings);
+        Ok(true)
+    }
+
+    pub fn save_file(&self, path: &Path) -> Result<(), EmbeddingCacheError> {
+        let cache_file = EmbeddingCacheFile {
+            backend: self.backend.identifier(),
+            embeddings: self.embeddings.borrow().clone(),
+        };
+        serde_json::to_writer(BufWriter::new(File::create(path)?), &cache_file)?;
+        Ok(())
+    }
+}
+
+impl SimilarityBackend for EmbeddingCache<'_> {
+    fn encode(&self, text_list: &[String]) -> Vec<Vec<f32>> {
+        self.preload(text_list);
+        let embeddings = self.embeddings.borrow();
+        text_list
+            .iter()
+            .map(|text| embeddings[&prep_for_value_measuring(text)].clone())
+            .collect()
+    }
+
+    fn identifier(&self) -> String {
+        self.backend.identifier()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    struct CountingBackend {
+        encoded: Cell<usize>,
+    }
+
+    impl SimilarityBackend for CountingBackend {
+        fn encode(&self, text_list: &[String]) -> Vec<Vec<f32>> {
+            self.encoded.set(self.encoded.get() + text_list.len());
+            text_list
+                .iter()
+                .map(|text| vec![text.len() as f32, 1f32])
+                .collect()
+        }
+
+        fn identifier(&self) -> String {
+            String::from("counting")
+        }
+    }
+
+    #[test]
+    fn texts_are_encoded_once() {
+        let backend = CountingBackend {
+            encoded: Cell::new(0),
+        };
+        let embedding_cache = EmbeddingCache::new(&backend);
+
+        embedding_cache.preload(&[
+            "Hello there.",
+            "{\\i1}Hello there.{\\i0}",
+            "General Kenobi!",
+        ]);
+        embedding_cache.compare("Hello there.", "General  Kenobi!");
+        embedding_cache.compare("Hello there.", "You are a bold one.");
+
+        assert_eq!(embedding_cache.len(), 3);
+        assert_eq!(backend.encoded.get(), 3);
+    }
+}
@@ -0,0 +1,125 @@
+// Pure-Rust similarity backend based on character n-grams
+//
+// Needs no model files or network access, so it can be used offline, in CI
+// and in unit tests. It only captures lexical overlap, lines that mean the
+// same thing but share no words will score low.
+
+use super::semantic_similarity::SimilarityBackend;
+use crate::alignment::text_processor::fnv1a;
+
+const DEFAULT_NGRAM_SIZE: usize = 3;
+const DEFAULT_DIMENSIONS: usize = 4096;
+
+pub struct LexicalSimilarity {
+    ngram_size: usize,
+    dimensions: usize,
+    /// Inverse document frequency per hashed n-gram, all n-grams weigh the
+    /// same when no corpus has been provided
+    idf: Option<Vec<f32>>,
+}
+
+impl Default for LexicalSimilarity {
+    fn default() -> Self {
+        Self {
+            ngram_size: DEFAULT_NGRAM_SIZE,
+            dimensions: DEFAULT_DIMENSIONS,
+            idf: None,
+        }
+    }
+}
+
+impl LexicalSimilarity {
+    /// Weighs the n-grams by their inverse document frequency in the given
+    /// texts (usually all events of both files), so common n-grams such as
+    /// " th" count less than rare ones.
+    pub fn with_corpus(mut self, texts: &[impl AsRef<str>]) -> Self {
+        let mut document_frequency = vec![0u32; self.dimensions];
+        for text in texts {
+            let mut seen = vec![false; self.dimensions];
+            for bucket in self.ngram_buckets(text.as_ref()) {
+                if !seen[bucket] {
+                    seen[bucket] = true;
+                    document_frequency[bucket] += 1;
+                }
+            }
+        }
+
+        let document_count = texts.len() as f32;
+        self.idf = Some(
+            document_frequency
+                .iter()
+                .map(|&df| ((1f32 + document_count) / (1f32 + df as f32)).ln() + 1f32)
+                .collect(),
+        );
+        self
+    }
+
+    fn ngram_buckets(&self, text: &str) -> Vec<usize> {
+        let padded: Vec<char> = format!(" {} ", text.to_lowercase()).chars().collect();
+        if padded.len() < self.ngram_size {
+            return vec![fnv1a(&padded) as usize % self.dimensions];
+        }
+
+        padded
+            .windows(self.ngram_size)
+            .map(|ngram| fnv1a(ngram) as usize % self.dimensions)
+            .collect()
+    }
+
+    fn encode_text(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0f32; self.dimensions];
+        for bucket in self.ngram_buckets(text) {
+            vector[bucket] += 1f32;
+        }
+        if let Some(idf) = &self.idf {
+            for (value, weight) in vector.iter_mut().zip(idf) {
+                *value *= weight;
+            }
+        }
+        vector
+    }
+}
+
+impl SimilarityBackend for LexicalSimilarity {
+    fn encode(&self, text_list: &[String]) -> Vec<Vec<f32>> {
+        text_list
+            .iter()
+            .map(|text| self.encode_text(text))
+            .collect()
+    }
+
+    fn identifier(&self) -> String {
+        let idf_hash = match &self.idf {
+            Some(idf) => idf.iter().fold(0u64, |hash, weight| {
+                hash.rotate_left(5) ^ weight.to_bits() as u64
+            }),
+            None => 0,
+        };
+        format!(
+            "lexical-{}-{}-{:016x}",
+            self.ngram_size, self.dimensions, idf_hash
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_ranks_overlapping_lines_higher() {
+        let lexical_similarity = LexicalSimilarity::default();
+        let similar = lexical_similarity.compare(
+            "Running right through the minefield? Is he an idiot?",
+            "He's running right through the minefield! What an idiot!",
+        );
+        let different = lexical_similarity.compare(
+            "Running right through the minefield? Is he an idiot?",
+            "I'll make dinner tonight.",
+        );
+
+        assert!((lexical_similarity.compare("Same line.", "Same line.") - 1f64).abs() < 1e-6);
+        assert!(similar > 0.5);
+        assert!(similar > different);
+    }
+}
@@ -1,44 +1,168 @@
+use std::path::{Path, PathBuf};
+
 use rust_bert::pipelines::sentence_embeddings::{
     SentenceEmbeddingsBuilder, SentenceEmbeddingsModel, SentenceEmbeddingsModelType,
 };
+use rust_bert::RustBertError;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SemanticModelError {
+    #[error("model directory does not exist ({0})")]
+    MissingDirectory(PathBuf),
+    #[error("model directory is missing {0}")]
+    MissingFile(PathBuf),
+    #[error("unknown sentence embeddings model type ({0})")]
+    UnknownModelType(String),
+    #[error("could not load sentence embeddings model ({0})")]
+    ModelError(RustBertError),
+}
+
+impl From<RustBertError> for SemanticModelError {
+    fn from(error: RustBertError) -> Self {
+        SemanticModelError::ModelError(error)
+    }
+}
+
+pub struct SemanticModelConfig {
+    pub model_type: SentenceEmbeddingsModelType,
+    /// Directory with a sentence-transformers model converted for rust-bert
+    /// (modules.json, config.json, tokenizer files and rust_model.ot). The
+    /// model is downloaded when no directory is given.
+    pub local_path: Option<PathBuf>,
+}
+
+impl Default for SemanticModelConfig {
+    fn default() -> Self {
+        Self {
+            model_type: SentenceEmbeddingsModelType::AllMiniLmL6V2,
+            local_path: None,
+        }
+    }
+}
 
 pub struct SemanticSimilarity {
     model: SentenceEmbeddingsModel,
+    identifier: String,
 }
 
 impl SemanticSimilarity {
-    pub fn default() -> Self {
-        Self {
-            model: SentenceEmbeddingsBuilder::remote(SentenceEmbeddingsModelType::AllMiniLmL6V2)
-                .create_model()
-                .unwrap(),
+    pub fn from_config(config: &SemanticModelConfig) -> Result<Self, SemanticModelError> {
+        let (model, identifier) = match &config.local_path {
+            Some(local_path) => {
+                check_model_directory(local_path, config.model_type)?;
+                (
+                    SentenceEmbeddingsBuilder::local(local_path).create_model()?,
+                    format!("{:?} ({})", config.model_type, local_path.display()),
+                )
+            }
+            None => (
+                SentenceEmbeddingsBuilder::remote(config.model_type).create_model()?,
+                format!("{:?}", config.model_type),
+            ),
+        };
+
+        Ok(Self { model, identifier })
+    }
+}
+
+pub fn parse_model_type(name: &str) -> Result<SentenceEmbeddingsModelType, SemanticModelError> {
+    match name.to_lowercase().as_str() {
+        "all-minilm-l6-v2" => Ok(SentenceEmbeddingsModelType::AllMiniLmL6V2),
+        "all-minilm-l12-v2" => Ok(SentenceEmbeddingsModelType::AllMiniLmL12V2),
+        "all-distilroberta-v1" => Ok(SentenceEmbeddingsModelType::AllDistilrobertaV1),
+        "bert-base-nli-mean-tokens" => Ok(SentenceEmbeddingsModelType::BertBaseNliMeanTokens),
+        "distiluse-base-multilingual-cased" => {
+            Ok(SentenceEmbeddingsModelType::DistiluseBaseMultilingualCased)
         }
+        "paraphrase-albert-small-v2" => Ok(SentenceEmbeddingsModelType::ParaphraseAlbertSmallV2),
+        "sentence-t5-base" => Ok(SentenceEmbeddingsModelType::SentenceT5Base),
+        _ => Err(SemanticModelError::UnknownModelType(name.to_string())),
     }
 }
 
-impl SemanticSimilarity {
-    pub fn compare(&self, text_a: &str, text_b: &str) -> f64 {
-        let vectors = self.model.encode(&[text_a, text_b]).unwrap();
+// rust-bert panics or returns vague errors on incomplete model directories, so
+// the files it is going to read are checked up front.
+fn check_model_directory(
+    local_path: &Path,
+    model_type: SentenceEmbeddingsModelType,
+) -> Result<(), SemanticModelError> {
+    if !local_path.is_dir() {
+        return Err(SemanticModelError::MissingDirectory(
+            local_path.to_path_buf(),
+        ));
+    }
+
+    let mut required_files = vec![
+        "modules.json",
+        "config.json",
+        "rust_model.ot",
+        "1_Pooling/config.json",
+    ];
+    match model_type {
+        SentenceEmbeddingsModelType::AllDistilrobertaV1 => {
+            required_files.extend(["vocab.json", "merges.txt"])
+        }
+        SentenceEmbeddingsModelType::ParaphraseAlbertSmallV2 => required_files.push("spiece.model"),
+        SentenceEmbeddingsModelType::SentenceT5Base => required_files.extend([
+            "spiece.model",
+            "2_Dense/config.json",
+            "2_Dense/rust_model.ot",
+        ]),
+        SentenceEmbeddingsModelType::DistiluseBaseMultilingualCased => {
+            required_files.extend(["vocab.txt", "2_Dense/config.json", "2_Dense/rust_model.ot"])
+        }
+        _ => required_files.push("vocab.txt"),
+    }
+
+    for file in required_files {
+        let file_path = local_path.join(file);
+        if !file_path.is_file() {
+            return Err(SemanticModelError::MissingFile(file_path));
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns subtitle text into vectors that can be compared with the cosine
+/// similarity. Implemented by the rust-bert sentence embedding model and by
+/// the lexical fallback that needs no model files.
+pub trait SimilarityBackend {
+    fn encode(&self, text_list: &[String]) -> Vec<Vec<f32>>;
+
+    /// Describes the model that produced the vectors, vectors of different
+    /// models can't be compared with each other.
+    fn identifier(&self) -> String;
+
+    fn compare(&self, text_a: &str, text_b: &str) -> f64 {
+        let vectors = self.encode(&[text_a.to_string(), text_b.to_string()]);
         cosine_distance(&vectors[0], &vectors[1])
     }
 
-    pub fn encode(&self, text_list: &Vec<String>) -> Vec<Vec<f32>> {
+    fn cosine_distance(&self, vec1: &[f32], vec2: &[f32]) -> f64 {
+        cosine_distance(vec1, vec2)
+    }
+}
+
+impl SimilarityBackend for SemanticSimilarity {
+    fn encode(&self, text_list: &[String]) -> Vec<Vec<f32>> {
         self.model.encode(text_list).unwrap()
     }
 
-    pub fn cosine_distance(&self, vec1: &Vec<f32>, vec2: &Vec<f32>) -> f64 {
-        cosine_distance(vec1, vec2)
+    fn identifier(&self) -> String {
+        self.identifier.clone()
     }
 }
 
-fn cosine_distance(vec1: &Vec<f32>, vec2: &Vec<f32>) -> f64 {
+fn cosine_distance(vec1: &[f32], vec2: &[f32]) -> f64 {
     let dot_product = dot_product(vec1, vec2);
     let root_sum_square1 = root_sum_square(vec1);
     let root_sum_square2 = root_sum_square(vec2);
     return dot_product as f64 / (root_sum_square1 * root_sum_square2);
 }
 
-fn root_sum_square(vec: &Vec<f32>) -> f64 {
+fn root_sum_square(vec: &[f32]) -> f64 {
     let mut sum_square = 0f32;
     for i in 0..vec.len() {
         sum_square += vec[i] * vec[i];
@@ -46,7 +170,7 @@ fn root_sum_square(vec: &Vec<f32>) -> f64 {
     (sum_square as f64).sqrt()
 }
 
-fn dot_product(vec1: &Vec<f32>, vec2: &Vec<f32>) -> f32 {
+fn dot_product(vec1: &[f32], vec2: &[f32]) -> f32 {
     let delta = vec1.len() as f32 - vec2.len() as f32;
     let shortest_vec = match delta {
         d if d < 0f32 => vec1,
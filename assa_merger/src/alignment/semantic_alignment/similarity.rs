@@ -1,6 +1,6 @@
 use std::cmp::min;
 
-use super::semantic_similarity::SemanticSimilarity;
+use super::semantic_similarity::SimilarityBackend;
 use crate::alignment::text_processor::{
     prep_for_value_measuring, prep_for_value_measuring_batch, remove_styling, split_groups,
 };
@@ -8,7 +8,7 @@ use crate::alignment::text_processor::{
 pub fn calc_split_similarity<'a>(
     line: &str,
     splits: &Vec<&'a String>,
-    semantic_similarity: &SemanticSimilarity,
+    semantic_similarity: &dyn SimilarityBackend,
 ) -> (f64, Vec<f64>, Vec<String>) {
     let potential_splits = split_groups(line);
     let split_vectors = semantic_similarity.encode(&prep_for_value_measuring_batch(splits));
@@ -44,7 +44,8 @@ pub fn calc_split_similarity<'a>(
 pub fn calc_merge_similarity<'a>(
     line_parts: &Vec<&'a String>,
     merged_line: &str,
-    semantic_similarity: &SemanticSimilarity,
+    merge_step_gain: f64,
+    semantic_similarity: &dyn SimilarityBackend,
 ) -> (f64, Vec<&'a String>) {
     // Returns into how many lines have been merged into the merged_line
     let mut merged_nr_of_lines = 0;
@@ -55,7 +56,7 @@ pub fn calc_merge_similarity<'a>(
 
     let mut highest_similarity = 0f64;
     let merged_line_vector =
-        &semantic_similarity.encode(&vec![prep_for_value_measuring(&merged_line)])[0];
+        &semantic_similarity.encode(&[prep_for_value_measuring(&merged_line)])[0];
 
     for i in 1..line_parts_count {
         let next_part = remove_styling(line_parts[i]);
@@ -72,11 +73,11 @@ pub fn calc_merge_similarity<'a>(
         }
 
         let potential_merge_vector =
-            &semantic_similarity.encode(&vec![prep_for_value_measuring(&potential_merge)])[0];
+            &semantic_similarity.encode(&[prep_for_value_measuring(&potential_merge)])[0];
         let similarity =
             semantic_similarity.cosine_distance(&potential_merge_vector, &merged_line_vector);
 
-        if similarity - highest_similarity > 0.1 {
+        if similarity - highest_similarity > merge_step_gain {
             highest_similarity = similarity;
             merged_nr_of_lines += 1;
         }
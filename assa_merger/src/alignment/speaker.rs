@@ -0,0 +1,133 @@
+use assa_parse::assa_file::event::Event;
+
+use super::text_processor::remove_styling;
+
+const DASHES: [char; 3] = ['-', '–', '—'];
+const SENTENCE_ENDS: [char; 7] = ['.', '?', '!', '…', '。', '？', '！'];
+
+/// Whether the text starts with the dash of a speaker turn. Hyphens always
+/// count, en and em dashes only with a space after them, "—and then" is an
+/// interruption.
+pub(crate) fn starts_with_turn_dash(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some('-') => true,
+        Some('–' | '—') => chars.next().is_some_and(char::is_whitespace),
+        _ => false,
+    }
+}
+
+/// Number of speakers in the line, lines like "- Hi\N- Hello" or "-Hi -Hello"
+/// have one per dash
+pub fn speaker_turns(text: &str) -> usize {
+    let text = remove_styling(text).replace(r"\N", "\n");
+    if !starts_with_turn_dash(text.trim_start()) {
+        return 1;
+    }
+    1 + turn_starts(&text).len()
+}
+
+/// Byte positions in `text` where a new speaker turn starts, not counting the
+/// first one. Only lines that start with a dash have turns.
+pub fn turn_starts(text: &str) -> Vec<usize> {
+    let trimmed = text.trim_start();
+    if !starts_with_turn_dash(trimmed) {
+        return Vec::new();
+    }
+    let leading = text.len() - trimmed.len();
+
+    // A dash after a line break or the end of a sentence, not "Well - maybe"
+    trimmed
+        .char_indices()
+        .skip(1)
+        .filter(|&(i, c)| {
+            let before = &trimmed[..i];
+            DASHES.contains(&c)
+                && starts_with_turn_dash(&trimmed[i..])
+                && (before.ends_with('\n')
+                    || before.ends_with(r"\N")
+                    || (before.ends_with(char::is_whitespace)
+                        && before.trim_end().ends_with(SENTENCE_ENDS)))
+        })
+        .map(|(i, _)| leading + i)
+        .collect()
+}
+
+/// Number of times the actor changes between consecutive events, events
+/// without an actor are skipped
+pub fn speaker_changes(events: &[&Event]) -> usize {
+    let speakers: Vec<&str> = events
+        .iter()
+        .map(|event| event.name.trim())
+        .filter(|name| !name.is_empty())
+        .collect();
+    speakers
+        .windows(2)
+        .filter(|pair| pair[0] != pair[1])
+        .count()
+}
+
+/// Whether the lines of several speakers fit into one line, which is only
+/// the case if it has a turn for each of them
+pub fn fits_speakers(parts: &[&Event], combined: &Event) -> bool {
+    speaker_changes(parts) < speaker_turns(&combined.text)
+}
+
+/// Removes the dashes that start speaker turns, "- Hi\N- Hello" becomes
+/// "Hi\NHello"
+pub fn strip_turn_dashes(text: &str) -> String {
+    let trimmed = text.trim_start();
+    if !starts_with_turn_dash(trimmed) {
+        return text.to_string();
+    }
+
+    let mut output = String::with_capacity(text.len());
+    let mut turn_start = 0;
+    for end in turn_starts(trimmed).into_iter().chain([trimmed.len()]) {
+        let turn = &trimmed[turn_start..end];
+        output.push_str(turn.trim_start_matches(DASHES).trim_start());
+        turn_start = end;
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::dialogue;
+
+    #[test]
+    fn speaker_turns_and_changes() {
+        assert_eq!(speaker_turns(r"- Hi.\N- Hello."), 2);
+        assert_eq!(speaker_turns("-Hi. -Hello. -Hey."), 3);
+        assert_eq!(speaker_turns("Well - maybe."), 1);
+        assert_eq!(turn_starts(r"- Hi.\N- Hello."), vec![7]);
+        assert_eq!(strip_turn_dashes(r"- Hi.\N- Hello."), r"Hi.\NHello.");
+        assert_eq!(strip_turn_dashes("Well - maybe."), "Well - maybe.");
+
+        // En and em dashes need a space to start a turn
+        assert_eq!(speaker_turns(r"— Hi.\N— Hello."), 2);
+        assert_eq!(speaker_turns(r"—And then he left.\N—Without a word?"), 1);
+        assert_eq!(speaker_turns("I thought—"), 1);
+        assert_eq!(
+            strip_turn_dashes("—and never came back."),
+            "—and never came back."
+        );
+
+        let events: Vec<Event> = [("Mio", "Hi."), ("", "Hm?"), ("Ritsu", "Hello.")]
+            .iter()
+            .map(|(name, text)| Event {
+                name: name.to_string(),
+                ..dialogue(1.0, 2.0, text)
+            })
+            .collect();
+        let parts: Vec<&Event> = events.iter().collect();
+        assert_eq!(speaker_changes(&parts), 1);
+
+        let mut combined = events[0].clone();
+        combined.text = String::from("Hi. Hello.");
+        assert!(!fits_speakers(&parts, &combined));
+        combined.text = String::from(r"- Hi.\N- Hello.");
+        assert!(fits_speakers(&parts, &combined));
+    }
+}
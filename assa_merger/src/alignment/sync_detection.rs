@@ -0,0 +1,320 @@
+// Detection of the timing offset between the dialogue and base scripts.
+//
+// TV and BD releases often differ by an inserted or removed scene, an
+// eyecatch or a commercial gap, so a single global offset does not fit the
+// whole episode. Lines that are (nearly) identical in both scripts are used
+// as anchors, and the episode is cut into segments wherever the offset
+// between the anchors changes.
+
+use std::fmt;
+
+use assa_parse::assa_file::event::Event;
+use chrono::{Duration, NaiveTime};
+use strsim::normalized_levenshtein;
+
+use super::config::AlignmentConfig;
+use super::semantic_alignment::semantic_similarity::SimilarityBackend;
+use super::text_processor::{prep_for_distance_measuring, prep_for_value_measuring_batch};
+
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    original_index: usize,
+    modified_index: usize,
+    delta_ms: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyncSegment {
+    /// Start of the segment on the timeline of the original events
+    pub start: NaiveTime,
+    /// End of the segment on the timeline of the original events
+    pub end: NaiveTime,
+    /// Time to add to an original event to get the matching modified event
+    pub offset: Duration,
+    pub anchor_count: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct OffsetMap {
+    pub segments: Vec<SyncSegment>,
+}
+
+impl OffsetMap {
+    pub fn segment_index_at(&self, time: NaiveTime) -> usize {
+        self.segments
+            .iter()
+            .rposition(|segment| segment.start <= time)
+            .unwrap_or(0)
+    }
+
+    pub fn offset_at(&self, time: NaiveTime) -> Duration {
+        match self.segments.is_empty() {
+            true => Duration::zero(),
+            false => self.segments[self.segment_index_at(time)].offset,
+        }
+    }
+}
+
+impl fmt::Display for SyncSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - {}  offset {:+.3}s  ({} anchors)",
+            self.start.format("%H:%M:%S%.3f"),
+            self.end.format("%H:%M:%S%.3f"),
+            self.offset.num_milliseconds() as f64 / 1000f64,
+            self.anchor_count
+        )
+    }
+}
+
+impl fmt::Display for OffsetMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Detected {} sync segment(s):", self.segments.len())?;
+        for (i, segment) in self.segments.iter().enumerate() {
+            writeln!(f, "  {}: {}", i + 1, segment)?;
+        }
+        Ok(())
+    }
+}
+
+/// Cuts the original events into segments that share the same timing offset
+/// towards the modified events. A new segment is only started once several
+/// consecutive anchors agree on the new offset, single outliers are ignored.
+pub fn detect_sync_segments(
+    original_events: &[&Event],
+    modified_events: &[&Event],
+    config: &AlignmentConfig,
+) -> OffsetMap {
+    let original_texts: Vec<String> = original_events
+        .iter()
+        .map(|event| prep_for_distance_measuring(&event.text))
+        .collect();
+    let modified_texts: Vec<String> = modified_events
+        .iter()
+        .map(|event| prep_for_distance_measuring(&event.text))
+        .collect();
+
+    let anchors = find_anchors(
+        original_events,
+        modified_events,
+        config.anchor_min_similarity,
+        |original_index, modified_index| {
+            let original_text = &original_texts[original_index];
+            let modified_text = &modified_texts[modified_index];
+            if original_text.len() < config.anchor_min_length
+                || modified_text.len() * 2 < original_text.len()
+                || original_text.len() * 2 < modified_text.len()
+            {
+                return 0f64;
+            }
+            normalized_levenshtein(original_text, modified_text)
+        },
+    );
+
+    build_offset_map(original_events, anchors, config)
+}
+
+/// Same as detect_sync_segments, but matches the anchors on meaning so it
+/// also works when both files are in a different language.
+pub fn detect_cross_lingual_sync_segments(
+    original_events: &[&Event],
+    modified_events: &[&Event],
+    semantic_similarity: &dyn SimilarityBackend,
+    config: &AlignmentConfig,
+) -> OffsetMap {
+    let original_texts =
+        prep_for_value_measuring_batch(&original_events.iter().map(|e| &e.text).collect());
+    let modified_texts =
+        prep_for_value_measuring_batch(&modified_events.iter().map(|e| &e.text).collect());
+    let original_vectors = semantic_similarity.encode(&original_texts);
+    let modified_vectors = semantic_similarity.encode(&modified_texts);
+
+    let anchors = find_anchors(
+        original_events,
+        modified_events,
+        config.anchor_min_semantic_similarity,
+        |original_index, modified_index| {
+            if original_texts[original_index].chars().count() < config.anchor_min_length {
+                return 0f64;
+            }
+            semantic_similarity.cosine_distance(
+                &original_vectors[original_index],
+                &modified_vectors[modified_index],
+            )
+        },
+    );
+
+    build_offset_map(original_events, anchors, config)
+}
+
+fn build_offset_map(
+    original_events: &[&Event],
+    anchors: Vec<Anchor>,
+    config: &AlignmentConfig,
+) -> OffsetMap {
+    let last_end = original_events
+        .iter()
+        .map(|event| event.end)
+        .max()
+        .unwrap_or(NaiveTime::MIN);
+
+    let mut anchor_groups: Vec<Vec<Anchor>> = Vec::new();
+    let mut current: Vec<Anchor> = Vec::new();
+    let mut pending: Vec<Anchor> = Vec::new();
+
+    for anchor in anchors {
+        if current.is_empty()
+            || (anchor.delta_ms - median_delta(&current)).abs() <= config.segment_tolerance_ms
+        {
+            current.push(anchor);
+            pending.clear();
+            continue;
+        }
+
+        if !pending.is_empty()
+            && (anchor.delta_ms - median_delta(&pending)).abs() > config.segment_tolerance_ms
+        {
+            pending.clear();
+        }
+        pending.push(anchor);
+
+        if pending.len() >= config.segment_min_anchors {
+            anchor_groups.push(current);
+            current = std::mem::take(&mut pending);
+        }
+    }
+    if !current.is_empty() {
+        anchor_groups.push(current);
+    }
+
+    if anchor_groups.is_empty() {
+        return OffsetMap {
+            segments: vec![SyncSegment {
+                start: NaiveTime::MIN,
+                end: last_end,
+                offset: Duration::zero(),
+                anchor_count: 0,
+            }],
+        };
+    }
+
+    let mut segments: Vec<SyncSegment> = Vec::with_capacity(anchor_groups.len());
+    for (i, group) in anchor_groups.iter().enumerate() {
+        let start = match i {
+            0 => NaiveTime::MIN,
+            _ => original_events[group[0].original_index].start,
+        };
+        if let Some(prev_segment) = segments.last_mut() {
+            prev_segment.end = start;
+        }
+        segments.push(SyncSegment {
+            start,
+            end: last_end,
+            offset: Duration::milliseconds(median_delta(group)),
+            anchor_count: group.len(),
+        });
+    }
+
+    OffsetMap { segments }
+}
+
+/// Returns the index of the modified event whose start is closest to the
+/// start of the original event once shifted by the given offset.
+pub fn closest_modified_index(
+    original_event: &Event,
+    modified_events: &[&Event],
+    offset: Duration,
+) -> Option<usize> {
+    let expected_start = original_event.start + offset;
+    modified_events
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, event)| (event.start - expected_start).num_milliseconds().abs())
+        .map(|(i, _)| i)
+}
+
+fn find_anchors(
+    original_events: &[&Event],
+    modified_events: &[&Event],
+    min_similarity: f64,
+    similarity: impl Fn(usize, usize) -> f64,
+) -> Vec<Anchor> {
+    let mut anchors: Vec<Anchor> = Vec::new();
+    for (original_index, original_event) in original_events.iter().enumerate() {
+        // An anchor has to be a unique match, repeated lines ("Yes.", "What?")
+        // can't tell us which part of the episode we're in.
+        let mut candidates = (0..modified_events.len())
+            .filter(|&modified_index| similarity(original_index, modified_index) >= min_similarity);
+
+        if let (Some(modified_index), None) = (candidates.next(), candidates.next()) {
+            // Anchors have to keep the order of both scripts
+            if let Some(last) = anchors.last() {
+                if modified_index <= last.modified_index {
+                    continue;
+                }
+            }
+            anchors.push(Anchor {
+                original_index,
+                modified_index,
+                delta_ms: (modified_events[modified_index].start - original_event.start)
+                    .num_milliseconds(),
+            });
+        }
+    }
+
+    anchors
+}
+
+fn median_delta(anchors: &[Anchor]) -> i64 {
+    let mut deltas: Vec<i64> = anchors.iter().map(|anchor| anchor.delta_ms).collect();
+    deltas.sort();
+    deltas[deltas.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::dialogue;
+
+    #[test]
+    fn detect_sync_segments_finds_inserted_break() {
+        let lines = [
+            "Wake up, it's already morning.",
+            "I don't want to go to school today.",
+            "You say that every single day.",
+            "Because it's true every single day!",
+            "Hurry up or we'll miss the train.",
+            "Fine, fine, I'm getting dressed.",
+        ];
+        let original: Vec<Event> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, text)| dialogue(i as f64 * 5f64, i as f64 * 5f64 + 3f64, text))
+            .collect();
+        // The modified script has a 20 second commercial break after the third line
+        let modified: Vec<Event> = lines
+            .iter()
+            .enumerate()
+            .map(|(i, text)| {
+                let start = (i * 5 + 2 + if i >= 3 { 20 } else { 0 }) as f64;
+                dialogue(start, start + 3f64, text)
+            })
+            .collect();
+
+        let offset_map = detect_sync_segments(
+            &original.iter().collect::<Vec<&Event>>(),
+            &modified.iter().collect::<Vec<&Event>>(),
+            &AlignmentConfig::default(),
+        );
+
+        assert_eq!(offset_map.segments.len(), 2);
+        assert_eq!(offset_map.segments[0].offset, Duration::seconds(2));
+        assert_eq!(offset_map.segments[1].offset, Duration::seconds(22));
+        assert_eq!(offset_map.segments[1].start, original[3].start);
+        assert_eq!(
+            offset_map.offset_at(original[4].start),
+            Duration::seconds(22)
+        );
+    }
+}
@@ -0,0 +1,326 @@
+// Synthetic test pairs for the aligner
+//
+// Turns any script into a made-up "other release" of it, so the aligner can be
+// tested without shipping copyrighted subtitles. Lines are split and merged at
+// punctuation, reworded with small word-level edits, dropped or joined by
+// invented lines, shifted and jittered in time, and their styles renamed.
+// The alignment of the two scripts is known and returned along with the
+// release, to be scored with Evaluation.
+
+use assa_parse::assa_file::{
+    event::Event,
+    override_tags::{parse_text, segments_to_string, TextSegment},
+    AssaFile,
+};
+use chrono::Duration;
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use super::aligned_pair::AlignedPair;
+use super::text_processor::split_indices_outside_tags;
+use super::AlignmentAction;
+use crate::merge::event_import::shift_time;
+use crate::merge::text_joining::{join_event_texts, JoinSeparator};
+
+/// Lines only the other release has, e.g. reactions the first one skipped
+const INSERTED_LINES: [&str; 6] = [
+    "Huh?",
+    "Hey!",
+    "Wait a second.",
+    "What?",
+    "No way...",
+    "Come on!",
+];
+/// Words added when rewording a line
+const FILLER_WORDS: [&str; 5] = ["really", "just", "so", "well,", "actually"];
+
+/// How strongly the other release differs, the rates are chances per line
+/// (per word for the word edits)
+#[derive(Debug, Clone)]
+pub struct Perturbation {
+    pub split_rate: f64,
+    /// Merged with the next line
+    pub merge_rate: f64,
+    /// A word is deleted, swapped with the next one or gets a filler word
+    pub word_edit_rate: f64,
+    pub drop_rate: f64,
+    /// An invented line is added after the line
+    pub insert_rate: f64,
+    /// Offset of the whole release
+    pub shift: Duration,
+    /// Largest random change of every start and end time
+    pub jitter: Duration,
+    pub rename_styles: bool,
+}
+
+impl Default for Perturbation {
+    fn default() -> Self {
+        Self {
+            split_rate: 0.1,
+            merge_rate: 0.1,
+            word_edit_rate: 0.1,
+            drop_rate: 0.05,
+            insert_rate: 0.05,
+            shift: Duration::milliseconds(2500),
+            jitter: Duration::milliseconds(120),
+            rename_styles: true,
+        }
+    }
+}
+
+pub struct SyntheticPair {
+    /// The other release, with only the events made from the selected events
+    /// of the source, sorted by start time
+    pub file: AssaFile,
+    /// Alignment of the selected events of the source (original) with the
+    /// events of `file` (modified)
+    pub pairs: Vec<AlignedPair>,
+}
+
+/// Makes another release of the events of `source` at `indices`, which have
+/// to be sorted by start time
+pub fn synthesize_pair(
+    source: &AssaFile,
+    indices: &[usize],
+    perturbation: &Perturbation,
+    rng: &mut impl Rng,
+) -> SyntheticPair {
+    // The new events with the pair they belong to, and the original indices
+    // of every pair
+    let mut events: Vec<(Event, usize)> = Vec::new();
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut position = 0;
+    while position < indices.len() {
+        let event = &source.events[indices[position]];
+        let next_event = indices.get(position + 1).map(|&i| &source.events[i]);
+        let roll: f64 = rng.gen();
+        let merge_limit = perturbation.drop_rate + perturbation.merge_rate;
+        let split_limit = merge_limit + perturbation.split_rate;
+
+        let (original_indices, new_events) = if roll < perturbation.drop_rate {
+            (vec![position], vec![])
+        } else if let (true, Some(next_event)) = (roll < merge_limit, next_event) {
+            let mut merged = event.clone();
+            merged.end = merged.end.max(next_event.end);
+            merged.text = join_event_texts(&[event, next_event], JoinSeparator::Space);
+            (vec![position, position + 1], vec![merged])
+        } else if let (true, Some(parts)) = (roll < split_limit, split_event(event)) {
+            (vec![position], parts)
+        } else {
+            (vec![position], vec![event.clone()])
+        };
+        position += original_indices.len();
+
+        let group = groups.len();
+        groups.push(original_indices);
+        for mut new_event in new_events {
+            new_event.text = reword(&new_event.text, perturbation.word_edit_rate, rng);
+            events.push((new_event, group));
+        }
+        if rng.gen_bool(perturbation.insert_rate) {
+            let mut inserted = event.clone();
+            inserted.start = event.end + Duration::milliseconds(200);
+            inserted.end = inserted.start + Duration::seconds(1);
+            inserted.text = INSERTED_LINES.choose(rng).unwrap().to_string();
+            events.push((inserted, groups.len()));
+            groups.push(vec![]);
+        }
+    }
+
+    let jitter_ms = perturbation.jitter.num_milliseconds();
+    for (event, _) in events.iter_mut() {
+        let mut offset =
+            || perturbation.shift + Duration::milliseconds(rng.gen_range(-jitter_ms..=jitter_ms));
+        event.start = shift_time(event.start, offset());
+        event.end = shift_time(event.end, offset()).max(event.start);
+        if perturbation.rename_styles {
+            event.style = renamed_style(&event.style);
+        }
+    }
+    events.sort_by_key(|(event, _)| event.start);
+
+    let mut modified_indices: Vec<Vec<usize>> = vec![vec![]; groups.len()];
+    for (modified_index, (_, group)) in events.iter().enumerate() {
+        modified_indices[*group].push(modified_index);
+    }
+    let pairs = groups
+        .into_iter()
+        .zip(modified_indices)
+        .map(|(original_indices, modified_indices)| {
+            let action = match original_indices.is_empty() {
+                true => AlignmentAction::Next,
+                false => AlignmentAction::None,
+            };
+            AlignedPair::new(original_indices, modified_indices, action)
+        })
+        .collect();
+
+    let mut styles = source.styles.clone();
+    if perturbation.rename_styles {
+        styles
+            .iter_mut()
+            .for_each(|style| style.name = renamed_style(&style.name));
+    }
+    SyntheticPair {
+        file: AssaFile {
+            script_info: source.script_info.clone(),
+            styles,
+            events: events.into_iter().map(|(event, _)| event).collect(),
+            ..AssaFile::default()
+        },
+        pairs,
+    }
+}
+
+fn renamed_style(style: &str) -> String {
+    format!("Alt {}", style)
+}
+
+/// Splits the event at the punctuation or line break closest to the middle of
+/// the text, the time is divided by the length of the parts
+fn split_event(event: &Event) -> Option<Vec<Event>> {
+    let middle = event.text.len() / 2;
+    let split_index = split_indices_outside_tags(&event.text)
+        .into_iter()
+        .min_by_key(|&index| index.abs_diff(middle))?;
+
+    let (first_text, second_text) = event.text.split_at(split_index);
+    let duration_ms = (event.end - event.start).num_milliseconds();
+    let first_ms = duration_ms * first_text.len() as i64 / event.text.len() as i64;
+    let mut first = event.clone();
+    let mut second = event.clone();
+    first.text = first_text.trim_end_matches(r"\N").trim_end().to_string();
+    first.end = event.start + Duration::milliseconds(first_ms);
+    second.text = second_text.trim_start().to_string();
+    second.start = first.end;
+    Some(vec![first, second])
+}
+
+/// Deletes, swaps and adds words between the override blocks, words with a
+/// line break are left alone
+fn reword(text: &str, word_edit_rate: f64, rng: &mut impl Rng) -> String {
+    let segments: Vec<TextSegment> = parse_text(text)
+        .into_iter()
+        .map(|segment| match segment {
+            TextSegment::Text(text) => TextSegment::Text(reword_words(&text, word_edit_rate, rng)),
+            _ => segment,
+        })
+        .collect();
+    segments_to_string(&segments)
+}
+
+fn reword_words(text: &str, word_edit_rate: f64, rng: &mut impl Rng) -> String {
+    let is_fixed = |word: &str| word.is_empty() || word.contains('\\');
+    let mut words: Vec<String> = text.split(' ').map(String::from).collect();
+    let mut i = 0;
+    while i < words.len() {
+        if is_fixed(&words[i]) || !rng.gen_bool(word_edit_rate) {
+            i += 1;
+            continue;
+        }
+        match rng.gen_range(0..3) {
+            0 if words.len() > 2 => {
+                words.remove(i);
+            }
+            1 if i + 1 < words.len() && !is_fixed(&words[i + 1]) => {
+                words.swap(i, i + 1);
+                i += 2;
+            }
+            _ => {
+                words.insert(i, FILLER_WORDS.choose(rng).unwrap().to_string());
+                i += 2;
+            }
+        }
+    }
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::dialogue;
+    use rand_chacha::rand_core::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn synthetic_pairs_cover_every_event_once() {
+        let source = AssaFile {
+            events: (0..40)
+                .map(|i| {
+                    dialogue(
+                        i as f64,
+                        i as f64 + 0.5,
+                        &format!("Line {}, said twice. Line {}!", i, i),
+                    )
+                })
+                .collect(),
+            ..AssaFile::default()
+        };
+        let indices: Vec<usize> = (0..source.events.len()).collect();
+        let perturbation = Perturbation {
+            split_rate: 0.2,
+            merge_rate: 0.2,
+            drop_rate: 0.1,
+            insert_rate: 0.1,
+            ..Perturbation::default()
+        };
+
+        let synthetic = synthesize_pair(
+            &source,
+            &indices,
+            &perturbation,
+            &mut ChaCha8Rng::seed_from_u64(7),
+        );
+        let mut original_indices: Vec<usize> = synthetic
+            .pairs
+            .iter()
+            .flat_map(|pair| pair.original_indices.clone())
+            .collect();
+        let mut modified_indices: Vec<usize> = synthetic
+            .pairs
+            .iter()
+            .flat_map(|pair| pair.modified_indices.clone())
+            .collect();
+        original_indices.sort();
+        modified_indices.sort();
+        assert_eq!(original_indices, indices);
+        assert_eq!(
+            modified_indices,
+            (0..synthetic.file.events.len()).collect::<Vec<_>>()
+        );
+        assert!(synthetic
+            .file
+            .events
+            .windows(2)
+            .all(|events| events[0].start <= events[1].start));
+        assert!(synthetic
+            .file
+            .events
+            .iter()
+            .all(|event| event.style == "Alt Default"));
+        // Every kind of change shows up with these rates
+        let shapes: Vec<(usize, usize)> = synthetic
+            .pairs
+            .iter()
+            .map(|pair| (pair.original_indices.len(), pair.modified_indices.len()))
+            .collect();
+        for shape in [(1, 1), (1, 2), (2, 1), (0, 1), (1, 0)] {
+            assert!(shapes.contains(&shape), "no {:?} pair", shape);
+        }
+    }
+
+    #[test]
+    fn override_blocks_stay_whole() {
+        let event = dialogue(1.0, 3.0, r"{\pos(100, 200)}Wait for me, I'm coming too");
+        let parts = split_event(&event).unwrap();
+        assert_eq!(parts[0].text, r"{\pos(100, 200)}Wait for me,");
+        assert_eq!(parts[1].text, "I'm coming too");
+
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        for _ in 0..20 {
+            let text = reword(&event.text, 0.5, &mut rng);
+            assert!(text.starts_with(r"{\pos(100, 200)}"), "{}", text);
+            assert_eq!(text.matches(['{', '}', '(', ')']).count(), 4, "{}", text);
+        }
+    }
+}
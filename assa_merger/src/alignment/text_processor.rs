@@ -1,6 +1,9 @@
+use assa_parse::assa_file::override_tags::{parse_text_with_ranges, TextSegment};
 use regex::Regex;
 use strsim::normalized_levenshtein;
 
+use super::speaker::strip_turn_dashes;
+
 pub fn remove_styling(text: &str) -> String {
     let re = Regex::new(r#"\{.*?\}"#).unwrap();
     re.replace_all(text, "").to_string().trim().to_string()
@@ -16,8 +19,21 @@ pub fn clean_spaces(text: impl AsRef<str>) -> String {
     output.trim().to_string()
 }
 
+// Stable hash, std's DefaultHasher is not guaranteed to be the same across
+// Rust releases.
+pub fn fnv1a(chars: &[char]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for c in chars {
+        for byte in (*c as u32).to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    hash
+}
+
 pub fn prep_for_value_measuring(text: impl AsRef<str>) -> String {
-    let mut output = remove_styling(text.as_ref().into());
+    let mut output = strip_turn_dashes(&remove_styling(text.as_ref()));
     output = output.replace(r#"\N"#, " ").replace("\"", "");
     output = clean_spaces(&output);
     output.to_string()
@@ -31,7 +47,7 @@ pub fn prep_for_value_measuring_batch(text_list: &Vec<impl AsRef<str>>) -> Vec<S
 }
 
 pub fn prep_for_distance_measuring(text: impl AsRef<str>) -> String {
-    let mut output = remove_styling(text.as_ref().into());
+    let mut output = remove_styling(text.as_ref());
     output = output.replace(r#"\N"#, " ");
     output = clean_spaces(&output);
 
@@ -42,7 +58,10 @@ pub fn prep_for_distance_measuring(text: impl AsRef<str>) -> String {
 
 fn split_indices(text: impl AsRef<str>) -> Vec<usize> {
     let mut indices: Vec<usize> = Vec::with_capacity(7);
-    for mat in Regex::new(r"[?!,.:;]+").unwrap().find_iter(text.as_ref()) {
+    for mat in Regex::new(r"[?!,.:;？！，。：；、…]+")
+        .unwrap()
+        .find_iter(text.as_ref())
+    {
         if mat.start() == 0 || mat.end() == text.as_ref().len() {
             continue;
         } else {
@@ -75,11 +94,32 @@ fn string_split_prep(text: impl AsRef<str>) -> String {
     output
 }
 
+/// Split points outside of the override blocks, commas in \pos(x,y) or
+/// \fad(a,b) aren't split points
+pub(crate) fn split_indices_outside_tags(text: &str) -> Vec<usize> {
+    let blocks: Vec<_> = parse_text_with_ranges(text)
+        .into_iter()
+        .filter(|(_, segment)| !matches!(segment, TextSegment::Text(_)))
+        .map(|(range, _)| range)
+        .collect();
+    split_indices(text)
+        .into_iter()
+        .filter(|&i| !blocks.iter().any(|range| range.start < i && i < range.end))
+        .collect()
+}
+
 pub fn split_groups(event_text: impl AsRef<str>) -> Vec<Vec<String>> {
+    let split_indices = split_indices(&event_text);
+    split_groups_at(event_text, &split_indices)
+}
+
+pub(crate) fn split_groups_at(
+    event_text: impl AsRef<str>,
+    split_indices: &[usize],
+) -> Vec<Vec<String>> {
     // splits on all split_values and combines it into all possible
     // combinations for which a line could've been split.
 
-    let split_indices = split_indices(&event_text);
     let split_value_count = split_indices.len();
     let possible_combinations = 1 << split_value_count;
     let mut result: Vec<Vec<String>> = Vec::new();
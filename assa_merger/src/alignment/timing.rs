@@ -0,0 +1,33 @@
+use std::cmp::{max, min};
+
+use assa_parse::assa_file::event::Event;
+use chrono::Duration;
+
+/// Overlap of the time spans covered by both groups of events, after shifting
+/// the original events by the offset. Returned as intersection over union, 1
+/// for identical timings and 0 when the spans don't touch.
+pub fn timing_similarity(
+    original_events: &[&Event],
+    modified_events: &[&Event],
+    offset: Duration,
+) -> f64 {
+    if original_events.is_empty() || modified_events.is_empty() {
+        return 0f64;
+    }
+
+    let original_start = original_events.iter().map(|e| e.start).min().unwrap() + offset;
+    let original_end = original_events.iter().map(|e| e.end).max().unwrap() + offset;
+    let modified_start = modified_events.iter().map(|e| e.start).min().unwrap();
+    let modified_end = modified_events.iter().map(|e| e.end).max().unwrap();
+
+    let intersection = (min(original_end, modified_end) - max(original_start, modified_start))
+        .num_milliseconds()
+        .max(0);
+    let union =
+        (max(original_end, modified_end) - min(original_start, modified_start)).num_milliseconds();
+
+    match union > 0 {
+        true => intersection as f64 / union as f64,
+        false => 0f64,
+    }
+}
@@ -0,0 +1,312 @@
+// Merging whole seasons at once
+//
+// The dialogue and base files are given as directories or glob patterns and
+// paired by the episode number in their file names, e.g.
+//   [Group] Show - 02 [1080p].ass, Show.S01E02.WEB.ass, Show [02].ass
+// Every pair is merged with the same configuration and written to a path made
+// from a template, a failing episode doesn't stop the others.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use thiserror::Error;
+
+use crate::alignment::aligned_pair::AlignedPair;
+use crate::event_processor::event_filter::{FilterError, Pattern};
+
+/// Extensions of the subtitle files picked from a directory
+const SUBTITLE_EXTENSIONS: [&str; 2] = ["ass", "ssa"];
+
+/// Tried in order, the first pattern that matches gives the episode number.
+/// Resolutions and years ([1080p], (2019)) don't match.
+const EPISODE_PATTERNS: [&str; 4] = [
+    r"(?i)S\d{1,2}\s?E(\d{1,3})",
+    r" - (\d{1,3})(?:v\d)?(?:[^\d]|$)",
+    r"(?i)(?:^|[^a-z\d])EP?\s?(\d{1,3})(?:v\d)?(?:[^\d]|$)",
+    r"\[(\d{1,3})(?:v\d)?\]",
+];
+
+/// CRCs like [55466DF0] are removed first, [E2A1B3C4] would look like E2
+const CRC_PATTERN: &str = r"\[[0-9A-Fa-f]{8}\]";
+
+#[derive(Error, Debug)]
+pub enum BatchError {
+    #[error("could not list files")]
+    IoError(std::io::Error),
+    #[error("invalid file pattern")]
+    PatternError(FilterError),
+    #[error("no subtitle files match {0}")]
+    NoFiles(String),
+}
+
+impl From<std::io::Error> for BatchError {
+    fn from(error: std::io::Error) -> Self {
+        BatchError::IoError(error)
+    }
+}
+
+impl From<FilterError> for BatchError {
+    fn from(error: FilterError) -> Self {
+        BatchError::PatternError(error)
+    }
+}
+
+/// Whether the path is a directory or glob pattern instead of a single file
+pub fn is_batch_path(path: &str) -> bool {
+    path.contains(['*', '?']) || Path::new(path).is_dir()
+}
+
+/// Subtitle files in a directory, or the files matching a glob pattern in its
+/// last component (`subs/*.en.ass`), sorted by name
+pub fn list_files(path: &str) -> Result<Vec<PathBuf>, BatchError> {
+    let path_buf = PathBuf::from(path);
+    let (directory, pattern) = match path_buf.is_dir() {
+        true => (path_buf, None),
+        false => {
+            let file_pattern = path_buf
+                .file_name()
+                .map(|name| Pattern::glob(&name.to_string_lossy()))
+                .transpose()?;
+            let directory = path_buf
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+            (directory, file_pattern)
+        }
+    };
+
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in fs::read_dir(&directory)? {
+        let file = entry?.path();
+        let file_name = file
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().to_string());
+        let matches = match &pattern {
+            Some(pattern) => pattern.is_match(&file_name),
+            None => file.extension().is_some_and(|extension| {
+                SUBTITLE_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str())
+            }),
+        };
+        if file.is_file() && matches {
+            files.push(file);
+        }
+    }
+    if files.is_empty() {
+        return Err(BatchError::NoFiles(path.to_string()));
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Episode number in the name of the file
+pub fn episode_number(file: &Path) -> Option<u32> {
+    let file_name = file.file_stem()?.to_string_lossy();
+    let file_name = Regex::new(CRC_PATTERN).unwrap().replace_all(&file_name, "");
+    EPISODE_PATTERNS.iter().find_map(|pattern| {
+        Regex::new(pattern)
+            .unwrap()
+            .captures(&file_name)
+            .and_then(|captures| captures[1].parse().ok())
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodePair {
+    pub episode: u32,
+    pub dialogue: PathBuf,
+    pub base: PathBuf,
+}
+
+impl EpisodePair {
+    /// Fills in {episode}, {dialogue} and {base} (the file names without
+    /// extension) in the template
+    pub fn output_path(&self, template: &str) -> PathBuf {
+        let stem = |path: &Path| {
+            path.file_stem()
+                .map_or(String::new(), |stem| stem.to_string_lossy().to_string())
+        };
+        PathBuf::from(
+            template
+                .replace("{episode}", &format!("{:02}", self.episode))
+                .replace("{dialogue}", &stem(&self.dialogue))
+                .replace("{base}", &stem(&self.base)),
+        )
+    }
+}
+
+/// Files of both sides with the same episode number, plus the files that
+/// couldn't be paired with the reason why
+pub fn pair_episodes(
+    dialogue_files: &[PathBuf],
+    base_files: &[PathBuf],
+) -> (Vec<EpisodePair>, Vec<(PathBuf, String)>) {
+    let mut unpaired: Vec<(PathBuf, String)> = Vec::new();
+    let mut by_episode = |files: &[PathBuf]| {
+        let mut episodes: BTreeMap<u32, PathBuf> = BTreeMap::new();
+        for file in files {
+            match episode_number(file) {
+                Some(episode) if episodes.contains_key(&episode) => {
+                    unpaired.push((file.clone(), format!("second file of episode {}", episode)))
+                }
+                Some(episode) => {
+                    episodes.insert(episode, file.clone());
+                }
+                None => unpaired.push((file.clone(), String::from("no episode number"))),
+            }
+        }
+        episodes
+    };
+    let dialogue_episodes = by_episode(dialogue_files);
+    let mut base_episodes = by_episode(base_files);
+
+    let mut pairs: Vec<EpisodePair> = Vec::new();
+    for (episode, dialogue) in dialogue_episodes {
+        match base_episodes.remove(&episode) {
+            Some(base) => pairs.push(EpisodePair {
+                episode,
+                dialogue,
+                base,
+            }),
+            None => unpaired.push((dialogue, format!("no base file for episode {}", episode))),
+        }
+    }
+    for (episode, base) in base_episodes {
+        unpaired.push((base, format!("no dialogue file for episode {}", episode)));
+    }
+    (pairs, unpaired)
+}
+
+/// How well the dialogue of one episode was matched
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EpisodeStats {
+    pub dialogue_lines: usize,
+    pub base_lines: usize,
+    /// Base lines that got dialogue
+    pub matched_lines: usize,
+    /// Matched pairs below the review threshold
+    pub low_confidence: usize,
+}
+
+impl EpisodeStats {
+    pub fn from_pairs(pairs: &[AlignedPair], review_threshold: f64) -> Self {
+        let matched_pairs = pairs.iter().filter(|pair| pair.is_matched());
+        Self {
+            dialogue_lines: pairs.iter().map(|pair| pair.original_indices.len()).sum(),
+            base_lines: pairs.iter().map(|pair| pair.modified_indices.len()).sum(),
+            matched_lines: matched_pairs
+                .clone()
+                .map(|pair| pair.modified_indices.len())
+                .sum(),
+            low_confidence: matched_pairs
+                .filter(|pair| pair.confidence < review_threshold)
+                .count(),
+        }
+    }
+
+    /// Share of the base lines that got dialogue
+    pub fn match_rate(&self) -> f64 {
+        match self.base_lines {
+            0 => 0f64,
+            base_lines => self.matched_lines as f64 / base_lines as f64,
+        }
+    }
+}
+
+pub struct EpisodeResult {
+    pub pair: EpisodePair,
+    pub outcome: Result<EpisodeStats, String>,
+}
+
+pub fn summary_table(results: &[EpisodeResult], unpaired: &[(PathBuf, String)]) -> String {
+    let mut table = format!(
+        "{:>7}  {:>8}  {:>5}  {:>7}  {:>6}  {:>6}  Result\n",
+        "Episode", "Dialogue", "Base", "Matched", "Rate", "Review"
+    );
+    for result in results {
+        let _ = match &result.outcome {
+            Ok(stats) => writeln!(
+                table,
+                "{:>7}  {:>8}  {:>5}  {:>7}  {:>5.1}%  {:>6}  ok",
+                result.pair.episode,
+                stats.dialogue_lines,
+                stats.base_lines,
+                stats.matched_lines,
+                stats.match_rate() * 100f64,
+                stats.low_confidence
+            ),
+            Err(error) => writeln!(
+                table,
+                "{:>7}  {:>8}  {:>5}  {:>7}  {:>6}  {:>6}  failed: {}",
+                result.pair.episode, "-", "-", "-", "-", "-", error
+            ),
+        };
+    }
+
+    let failed_count = results
+        .iter()
+        .filter(|result| result.outcome.is_err())
+        .count();
+    let _ = writeln!(
+        table,
+        "\n{} of {} episodes merged",
+        results.len() - failed_count,
+        results.len()
+    );
+    for (file, reason) in unpaired {
+        let _ = writeln!(table, "Skipped {}: {}", file.display(), reason);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn episodes_are_paired_by_number() {
+        let files = |names: &[&str]| -> Vec<PathBuf> { names.iter().map(PathBuf::from).collect() };
+        let numbers: Vec<Option<u32>> = files(&[
+            "[Group] Show - 02 [1080p][55466DF0].ass",
+            "Show.S01E03.WEB.en.ass",
+            "Show Ep 4.ass",
+            "Show [05v2].ass",
+            "Show (2019) [1080p].ass",
+        ])
+        .iter()
+        .map(|file| episode_number(file))
+        .collect();
+        assert_eq!(numbers, vec![Some(2), Some(3), Some(4), Some(5), None]);
+
+        // CRCs can start like an episode number
+        let numbers: Vec<Option<u32>> = files(&[
+            "[Group] Show - 12 [1080p][E2A1B3C4].ass",
+            "[Group] Show - 07 [1E23ABCD].ass",
+            "Show E08 [A3E4F5B6].ass",
+        ])
+        .iter()
+        .map(|file| episode_number(file))
+        .collect();
+        assert_eq!(numbers, vec![Some(12), Some(7), Some(8)]);
+
+        let (pairs, unpaired) = pair_episodes(
+            &files(&["dialogue/Show - 01.ass", "dialogue/Show - 02.ass"]),
+            &files(&["base/Show E02.ass", "base/Show E03.ass"]),
+        );
+        assert_eq!(
+            pairs,
+            vec![EpisodePair {
+                episode: 2,
+                dialogue: PathBuf::from("dialogue/Show - 02.ass"),
+                base: PathBuf::from("base/Show E02.ass"),
+            }]
+        );
+        assert_eq!(unpaired.len(), 2);
+        assert_eq!(
+            pairs[0].output_path("merged/{base} [{episode}].ass"),
+            PathBuf::from("merged/Show E02 [02].ass")
+        );
+    }
+}
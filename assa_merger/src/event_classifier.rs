@@ -0,0 +1,492 @@
+// Tells dialogue apart from signs, songs and comments
+//
+// Every group names its styles differently, so instead of relying on a list of
+// dialogue style names each event is scored on a few features: the name of its
+// style, its override tags (\pos, \k, drawings), alignment, font, layer, how
+// many events share its exact timing and the shape of its text. The labels of
+// the events are then summed up per style, which the user can override.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use assa_parse::assa_file::{
+    event::Event,
+    override_tags::{OverrideTag, TextSegment},
+    AssaFile,
+};
+use thiserror::Error;
+
+/// Style name parts that point to a kind of event, short ones only count as a
+/// whole word ("OP Romaji", not "Opening")
+const DIALOGUE_STYLE_WORDS: [&str; 10] = [
+    "default",
+    "main",
+    "dialog",
+    "alt",
+    "alternate",
+    "flashback",
+    "overlap",
+    "narrat",
+    "thought",
+    "italic",
+];
+const SIGN_STYLE_WORDS: [&str; 7] = ["sign", "title", "card", "screen", "ts", "note", "caption"];
+const SONG_STYLE_WORDS: [&str; 10] = [
+    "op", "ed", "song", "kara", "lyric", "insert", "romaji", "kanji", "opening", "ending",
+];
+const SHORT_WORD_LENGTH: usize = 3;
+
+/// Tags that are hardly ever used on dialogue
+const TYPESETTING_TAGS: [&str; 12] = [
+    "pos", "move", "org", "clip", "iclip", "frx", "fry", "frz", "fr", "fax", "fay", "t",
+];
+const KARAOKE_TAGS: [&str; 4] = ["k", "K", "kf", "ko"];
+
+/// Events sharing their timing with this many events or more are typesetting
+/// layers
+const SAME_TIMING_GROUP: usize = 3;
+/// Words a line needs to read as a sentence
+const SENTENCE_WORDS: usize = 4;
+
+const STYLE_NAME_WEIGHT: f64 = 1.5;
+const TAG_WEIGHT: f64 = 3.0;
+const FEATURE_WEIGHT: f64 = 1.0;
+const WEAK_FEATURE_WEIGHT: f64 = 0.5;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ClassificationError {
+    #[error("unknown event kind ({0}), expected dialogue, sign, song or comment")]
+    UnknownKind(String),
+    #[error("expected style=kind, got {0}")]
+    FormatError(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    Dialogue,
+    Sign,
+    /// Songs and karaoke
+    Song,
+    Comment,
+}
+
+impl FromStr for EventKind {
+    type Err = ClassificationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "dialogue" => Ok(EventKind::Dialogue),
+            "sign" | "signs" => Ok(EventKind::Sign),
+            "song" | "songs" | "karaoke" => Ok(EventKind::Song),
+            "comment" => Ok(EventKind::Comment),
+            _ => Err(ClassificationError::UnknownKind(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            EventKind::Dialogue => "dialogue",
+            EventKind::Sign => "sign",
+            EventKind::Song => "song",
+            EventKind::Comment => "comment",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Kind of a style set by the user, parsed from `style=kind`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleOverride {
+    pub style: String,
+    pub kind: EventKind,
+}
+
+impl FromStr for StyleOverride {
+    type Err = ClassificationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.rsplit_once('=') {
+            Some((style, kind)) => Ok(StyleOverride {
+                style: style.trim().to_string(),
+                kind: EventKind::from_str(kind)?,
+            }),
+            None => Err(ClassificationError::FormatError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleClassification {
+    pub style: String,
+    pub kind: EventKind,
+    /// Number of events labelled dialogue, sign, song and comment
+    pub counts: [usize; 4],
+    pub overridden: bool,
+}
+
+#[derive(Debug, Default)]
+struct Scores {
+    dialogue: f64,
+    sign: f64,
+    song: f64,
+}
+
+impl Scores {
+    fn add(&mut self, kind: EventKind, weight: f64) {
+        match kind {
+            EventKind::Dialogue => self.dialogue += weight,
+            EventKind::Sign => self.sign += weight,
+            EventKind::Song => self.song += weight,
+            EventKind::Comment => {}
+        }
+    }
+
+    // Ties go to dialogue, then signs
+    fn kind(&self) -> EventKind {
+        if self.dialogue >= self.sign && self.dialogue >= self.song {
+            EventKind::Dialogue
+        } else if self.sign >= self.song {
+            EventKind::Sign
+        } else {
+            EventKind::Song
+        }
+    }
+}
+
+/// Labels every event of the file, in the same order as `file.events`
+pub fn classify_events(file: &AssaFile) -> Vec<EventKind> {
+    let mut timing_groups: HashMap<(chrono::NaiveTime, chrono::NaiveTime), usize> = HashMap::new();
+    for event in file.events.iter().filter(|event| !event.comment) {
+        *timing_groups.entry((event.start, event.end)).or_default() += 1;
+    }
+
+    let mut font_counts: HashMap<&str, usize> = HashMap::new();
+    for event in file.events.iter().filter(|event| !event.comment) {
+        if let Some(style) = file.styles.iter().find(|style| style.name == event.style) {
+            *font_counts.entry(style.fontname.as_str()).or_default() += 1;
+        }
+    }
+    let main_font = font_counts
+        .into_iter()
+        .max_by_key(|&(_, count)| count)
+        .map(|(font, _)| font);
+
+    file.events
+        .iter()
+        .map(|event| {
+            if event.comment {
+                return EventKind::Comment;
+            }
+            let style = file.styles.iter().find(|style| style.name == event.style);
+            let mut scores = Scores {
+                dialogue: WEAK_FEATURE_WEIGHT,
+                ..Scores::default()
+            };
+
+            if let Some(kind) = style_name_kind(&event.style) {
+                scores.add(kind, STYLE_NAME_WEIGHT);
+            }
+            score_tags(event, style.map_or(2, |style| style.alignment), &mut scores);
+
+            if event.layer > 0 {
+                scores.add(EventKind::Sign, WEAK_FEATURE_WEIGHT);
+            }
+            if timing_groups[&(event.start, event.end)] >= SAME_TIMING_GROUP {
+                scores.add(EventKind::Sign, FEATURE_WEIGHT);
+            }
+            if let (Some(style), Some(main_font)) = (style, main_font) {
+                if style.fontname != main_font {
+                    scores.add(EventKind::Sign, WEAK_FEATURE_WEIGHT);
+                    scores.add(EventKind::Song, WEAK_FEATURE_WEIGHT);
+                }
+            }
+            score_text(&event.plain_text(), &mut scores);
+
+            scores.kind()
+        })
+        .collect()
+}
+
+fn style_name_kind(style_name: &str) -> Option<EventKind> {
+    let words: Vec<String> = style_name
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect();
+    let matches = |style_words: &[&str]| {
+        style_words.iter().any(|style_word| {
+            words
+                .iter()
+                .any(|word| match style_word.len() <= SHORT_WORD_LENGTH {
+                    true => word == style_word,
+                    false => word.contains(style_word),
+                })
+        })
+    };
+
+    if matches(&SONG_STYLE_WORDS) {
+        Some(EventKind::Song)
+    } else if matches(&SIGN_STYLE_WORDS) {
+        Some(EventKind::Sign)
+    } else if matches(&DIALOGUE_STYLE_WORDS) {
+        Some(EventKind::Dialogue)
+    } else {
+        None
+    }
+}
+
+fn score_tags(event: &Event, style_alignment: u8, scores: &mut Scores) {
+    let tags: Vec<OverrideTag> = event
+        .text_segments()
+        .into_iter()
+        .filter_map(|segment| match segment {
+            TextSegment::Tags(tags) => Some(tags),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+
+    if tags
+        .iter()
+        .any(|tag| tag.name == "p" && !matches!(tag.value.as_str(), "" | "0"))
+    {
+        scores.add(EventKind::Sign, TAG_WEIGHT);
+    }
+    if tags
+        .iter()
+        .any(|tag| KARAOKE_TAGS.contains(&tag.name.as_str()))
+        || event.effect.to_lowercase().contains("karaoke")
+    {
+        scores.add(EventKind::Song, TAG_WEIGHT);
+    }
+    let typesetting_tags = tags
+        .iter()
+        .filter(|tag| TYPESETTING_TAGS.contains(&tag.name.as_str()))
+        .count();
+    scores.add(
+        EventKind::Sign,
+        FEATURE_WEIGHT * typesetting_tags.min(3) as f64,
+    );
+
+    // Dialogue sits at the bottom, or at the top when it's in the way of signs
+    let alignment = tags
+        .iter()
+        .rev()
+        .find(|tag| tag.name == "an")
+        .and_then(|tag| tag.value.parse().ok())
+        .unwrap_or(style_alignment);
+    if !matches!(alignment, 2 | 8) {
+        scores.add(EventKind::Sign, FEATURE_WEIGHT);
+    }
+}
+
+fn score_text(plain_text: &str, scores: &mut Scores) {
+    let text = plain_text.replace(r"\N", " ").replace(r"\n", " ");
+    let words = text.split_whitespace().count();
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+
+    if letters.is_empty() {
+        scores.add(EventKind::Sign, WEAK_FEATURE_WEIGHT);
+        return;
+    }
+    if words >= SENTENCE_WORDS && letters.iter().any(|c| c.is_lowercase()) {
+        scores.add(EventKind::Dialogue, FEATURE_WEIGHT);
+    }
+    if text
+        .trim_end()
+        .ends_with(['.', '?', '!', ',', '…', '。', '？', '！'])
+    {
+        scores.add(EventKind::Dialogue, WEAK_FEATURE_WEIGHT);
+    }
+    if letters.iter().all(|c| !c.is_lowercase()) && letters.iter().any(|c| c.is_uppercase()) {
+        scores.add(EventKind::Sign, WEAK_FEATURE_WEIGHT);
+    }
+}
+
+/// Sums up the labels of the events per style, in the order the styles are
+/// first used. A style gets the kind most of its (non-comment) events have,
+/// unless it's overridden.
+pub fn classify_styles(
+    events: &[Event],
+    kinds: &[EventKind],
+    overrides: &[StyleOverride],
+) -> Vec<StyleClassification> {
+    let mut classifications: Vec<StyleClassification> = Vec::new();
+    for (event, &kind) in events.iter().zip(kinds) {
+        let position = match classifications
+            .iter()
+            .position(|classification| classification.style == event.style)
+        {
+            Some(position) => position,
+            None => {
+                classifications.push(StyleClassification {
+                    style: event.style.clone(),
+                    kind: EventKind::Comment,
+                    counts: [0; 4],
+                    overridden: false,
+                });
+                classifications.len() - 1
+            }
+        };
+        classifications[position].counts[kind_index(kind)] += 1;
+    }
+
+    for classification in &mut classifications {
+        let style_override = overrides
+            .iter()
+            .rev()
+            .find(|style_override| style_override.style == classification.style);
+        match style_override {
+            Some(style_override) => {
+                classification.kind = style_override.kind;
+                classification.overridden = true;
+            }
+            None => {
+                let counts = classification.counts;
+                classification.kind = [EventKind::Dialogue, EventKind::Sign, EventKind::Song]
+                    .into_iter()
+                    .rev()
+                    .max_by_key(|&kind| counts[kind_index(kind)])
+                    .filter(|&kind| counts[kind_index(kind)] > 0)
+                    .unwrap_or(EventKind::Comment);
+            }
+        }
+    }
+    classifications
+}
+
+fn kind_index(kind: EventKind) -> usize {
+    match kind {
+        EventKind::Dialogue => 0,
+        EventKind::Sign => 1,
+        EventKind::Song => 2,
+        EventKind::Comment => 3,
+    }
+}
+
+/// Names of the styles classified as dialogue
+pub fn dialogue_styles(classifications: &[StyleClassification]) -> Vec<String> {
+    styles_of_kinds(classifications, &[EventKind::Dialogue])
+}
+
+/// Names of the styles classified as one of the kinds
+pub fn styles_of_kinds(
+    classifications: &[StyleClassification],
+    kinds: &[EventKind],
+) -> Vec<String> {
+    classifications
+        .iter()
+        .filter(|classification| kinds.contains(&classification.kind))
+        .map(|classification| classification.style.clone())
+        .collect()
+}
+
+pub fn classification_report(title: &str, classifications: &[StyleClassification]) -> String {
+    let mut report = format!(
+        "{}\n  {:<24} {:>8} {:>8} {:>8} {:>8}  kind\n",
+        title, "style", "dialogue", "sign", "song", "comment"
+    );
+    for classification in classifications {
+        let [dialogue, sign, song, comment] = classification.counts;
+        let _ = writeln!(
+            report,
+            "  {:<24} {:>8} {:>8} {:>8} {:>8}  {}{}",
+            classification.style,
+            dialogue,
+            sign,
+            song,
+            comment,
+            classification.kind,
+            match classification.overridden {
+                true => " (overridden)",
+                false => "",
+            }
+        );
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::dialogue;
+    use assa_parse::assa_file::style::Style;
+
+    fn event(style: &str, start: f64, end: f64, text: &str) -> Event {
+        Event {
+            style: style.to_string(),
+            ..dialogue(start, end, text)
+        }
+    }
+
+    #[test]
+    fn classify_events_labels_dialogue_signs_and_songs() {
+        let file = AssaFile {
+            styles: ["Main", "Sign", "Style1"]
+                .iter()
+                .map(|name| Style {
+                    name: name.to_string(),
+                    alignment: 2,
+                    ..Style::default()
+                })
+                .collect(),
+            events: vec![
+                event("Main", 1.0, 3.0, "Where did you put my keys?"),
+                event("Style1", 4.0, 6.0, "I think they're on the table."),
+                event("Style1", 4.0, 6.0, r"{\pos(620,80)\frz12}BAKERY"),
+                event("Main", 7.0, 8.0, r"{\an7\pos(10,10)}CLOSED"),
+                event("Sign", 9.0, 10.0, "Chapter 2"),
+                event(
+                    "Style1",
+                    20.0,
+                    25.0,
+                    r"{\k20}Ka{\k30}ze {\k25}ga {\k40}fu{\k20}ku",
+                ),
+                Event {
+                    comment: true,
+                    ..event("Main", 1.0, 3.0, "Check this")
+                },
+            ],
+            ..AssaFile::default()
+        };
+
+        assert_eq!(
+            classify_events(&file),
+            vec![
+                EventKind::Dialogue,
+                EventKind::Dialogue,
+                EventKind::Sign,
+                EventKind::Sign,
+                EventKind::Sign,
+                EventKind::Song,
+                EventKind::Comment,
+            ]
+        );
+    }
+
+    #[test]
+    fn classify_styles_uses_majority_and_overrides() {
+        let events: Vec<Event> = ["Main", "Main", "Main", "OP"]
+            .iter()
+            .map(|style| event(style, 1.0, 2.0, "Text"))
+            .collect();
+        let kinds = [
+            EventKind::Dialogue,
+            EventKind::Sign,
+            EventKind::Dialogue,
+            EventKind::Dialogue,
+        ];
+        let overrides = [StyleOverride::from_str("OP=song").unwrap()];
+
+        let classifications = classify_styles(&events, &kinds, &overrides);
+        assert_eq!(classifications[0].kind, EventKind::Dialogue);
+        assert_eq!(classifications[0].counts, [2, 1, 0, 0]);
+        assert_eq!(classifications[1].kind, EventKind::Song);
+        assert!(classifications[1].overridden);
+        assert_eq!(dialogue_styles(&classifications), vec!["Main"]);
+        assert!(StyleOverride::from_str("OP").is_err());
+    }
+}
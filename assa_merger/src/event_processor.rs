@@ -1,5 +1,7 @@
 use assa_parse::assa_file::event::Event;
 
+pub mod event_filter;
+
 pub fn filter_events_by_style<'events>(
     events: &'events Vec<Event>,
     styles: &Vec<String>,
@@ -14,3 +16,22 @@ pub fn filter_events_by_style<'events>(
 pub fn get_text_of_events<'a>(events: &'a [&Event]) -> Vec<&'a String> {
     events.iter().map(|event| &event.text).collect()
 }
+
+/// Same as filter_events_by_style, but returns the positions of the events so
+/// they can be traced back to the full list of events.
+pub fn filter_event_indices_by_style(
+    events: &[Event],
+    styles: &[String],
+    keep_comments: bool,
+) -> Vec<usize> {
+    events
+        .iter()
+        .enumerate()
+        .filter(|(_, event)| (keep_comments || !event.comment) && styles.contains(&event.style))
+        .map(|(i, _)| i)
+        .collect()
+}
+
+pub fn get_events_by_indices<'a>(events: &'a [Event], indices: &[usize]) -> Vec<&'a Event> {
+    indices.iter().map(|&i| &events[i]).collect()
+}
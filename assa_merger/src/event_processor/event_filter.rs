@@ -0,0 +1,342 @@
+// Conditions for selecting events, combined with and/or/not
+//
+// Filters can be built in code or parsed from a query, e.g.
+//   style:Default* and not actor:Narrator
+//   (style~"^(Main|Flashback)$" or tag:i) and time:0:01:30-0:22:00
+//   layer:0-1 and not comment:yes and text~"\?$"
+// `key:value` matches a glob, `key~value` a regular expression. Values with
+// spaces or parentheses have to be quoted.
+
+use std::str::FromStr;
+
+use assa_parse::assa_file::{event::Event, override_tags::TextSegment};
+use chrono::NaiveTime;
+use regex::Regex;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum FilterError {
+    #[error("malformed filter ({0})")]
+    FormatError(String),
+    #[error("invalid regular expression")]
+    RegexError(regex::Error),
+}
+
+impl From<regex::Error> for FilterError {
+    fn from(error: regex::Error) -> Self {
+        FilterError::RegexError(error)
+    }
+}
+
+impl From<chrono::ParseError> for FilterError {
+    fn from(error: chrono::ParseError) -> Self {
+        FilterError::FormatError(error.to_string())
+    }
+}
+
+/// A glob (`Default*`, `Sign?`) or regular expression matched against a field
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    pub fn glob(glob: &str) -> Result<Self, FilterError> {
+        let mut pattern = String::from("^");
+        for c in glob.chars() {
+            match c {
+                '*' => pattern.push_str(".*"),
+                '?' => pattern.push('.'),
+                c => pattern.push_str(&regex::escape(&c.to_string())),
+            }
+        }
+        pattern.push('$');
+        Ok(Self(Regex::new(&pattern)?))
+    }
+
+    pub fn regex(regex: &str) -> Result<Self, FilterError> {
+        Ok(Self(Regex::new(regex)?))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum EventFilter {
+    Style(Pattern),
+    /// Matches the name field of the event
+    Actor(Pattern),
+    Effect(Pattern),
+    Layer {
+        min: u8,
+        max: u8,
+    },
+    /// Events that are (partly) shown between start and end
+    Time {
+        start: NaiveTime,
+        end: NaiveTime,
+    },
+    /// Matches the text without override tags
+    Text(Pattern),
+    /// The text contains an override tag with this name, e.g. `pos`
+    Tag(String),
+    Comment(bool),
+    All(Vec<EventFilter>),
+    Any(Vec<EventFilter>),
+    Not(Box<EventFilter>),
+}
+
+impl EventFilter {
+    pub fn matches(&self, event: &Event) -> bool {
+        match self {
+            EventFilter::Style(pattern) => pattern.is_match(&event.style),
+            EventFilter::Actor(pattern) => pattern.is_match(&event.name),
+            EventFilter::Effect(pattern) => pattern.is_match(&event.effect),
+            EventFilter::Layer { min, max } => (*min..=*max).contains(&event.layer),
+            EventFilter::Time { start, end } => event.start <= *end && event.end >= *start,
+            EventFilter::Text(pattern) => pattern.is_match(&event.plain_text()),
+            EventFilter::Tag(name) => event.text_segments().iter().any(|segment| match segment {
+                TextSegment::Tags(tags) => tags.iter().any(|tag| tag.name == *name),
+                _ => false,
+            }),
+            EventFilter::Comment(comment) => event.comment == *comment,
+            EventFilter::All(filters) => filters.iter().all(|filter| filter.matches(event)),
+            EventFilter::Any(filters) => filters.iter().any(|filter| filter.matches(event)),
+            EventFilter::Not(filter) => !filter.matches(event),
+        }
+    }
+
+    pub fn and(self, other: EventFilter) -> Self {
+        match self {
+            EventFilter::All(mut filters) => {
+                filters.push(other);
+                EventFilter::All(filters)
+            }
+            filter => EventFilter::All(vec![filter, other]),
+        }
+    }
+
+    pub fn or(self, other: EventFilter) -> Self {
+        match self {
+            EventFilter::Any(mut filters) => {
+                filters.push(other);
+                EventFilter::Any(filters)
+            }
+            filter => EventFilter::Any(vec![filter, other]),
+        }
+    }
+
+    pub fn negate(self) -> Self {
+        EventFilter::Not(Box::new(self))
+    }
+}
+
+impl FromStr for EventFilter {
+    type Err = FilterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = QueryParser {
+            tokens: &tokens,
+            position: 0,
+        };
+        let filter = parser.parse_or()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => Err(FilterError::FormatError(format!("unexpected {}", token))),
+        }
+    }
+}
+
+// Splits a query into parentheses, keywords and conditions, quotes are
+// removed from the values
+fn tokenize(query: &str) -> Result<Vec<String>, FilterError> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+
+    for c in query.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if quoted => token.push(c),
+            '(' | ')' if token.is_empty() || c == ')' => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+                tokens.push(c.to_string());
+            }
+            c if c.is_whitespace() => {
+                if !token.is_empty() {
+                    tokens.push(std::mem::take(&mut token));
+                }
+            }
+            c => token.push(c),
+        }
+    }
+    if quoted {
+        return Err(FilterError::FormatError(String::from("unclosed quote")));
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct QueryParser<'a> {
+    tokens: &'a [String],
+    position: usize,
+}
+
+impl<'a> QueryParser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|token| token.as_str())
+    }
+
+    fn advance(&mut self) -> Option<&'a str> {
+        self.position += 1;
+        self.tokens
+            .get(self.position - 1)
+            .map(|token| token.as_str())
+    }
+
+    fn parse_or(&mut self) -> Result<EventFilter, FilterError> {
+        let mut filter = self.parse_and()?;
+        while self
+            .peek()
+            .is_some_and(|token| token.eq_ignore_ascii_case("or"))
+        {
+            self.advance();
+            filter = filter.or(self.parse_and()?);
+        }
+        Ok(filter)
+    }
+
+    // Conditions next to each other without a keyword have to match as well
+    fn parse_and(&mut self) -> Result<EventFilter, FilterError> {
+        let mut filter = self.parse_not()?;
+        while let Some(token) = self.peek() {
+            if token.eq_ignore_ascii_case("or") || token == ")" {
+                break;
+            }
+            if token.eq_ignore_ascii_case("and") {
+                self.advance();
+            }
+            filter = filter.and(self.parse_not()?);
+        }
+        Ok(filter)
+    }
+
+    fn parse_not(&mut self) -> Result<EventFilter, FilterError> {
+        match self.advance() {
+            Some(token) if token.eq_ignore_ascii_case("not") => Ok(self.parse_not()?.negate()),
+            Some("(") => {
+                let filter = self.parse_or()?;
+                match self.advance() {
+                    Some(")") => Ok(filter),
+                    _ => Err(FilterError::FormatError(String::from(
+                        "unclosed parenthesis",
+                    ))),
+                }
+            }
+            Some(token) => parse_condition(token),
+            None => Err(FilterError::FormatError(String::from(
+                "expected a condition",
+            ))),
+        }
+    }
+}
+
+fn parse_condition(condition: &str) -> Result<EventFilter, FilterError> {
+    let separator = condition.find([':', '~']).ok_or_else(|| {
+        FilterError::FormatError(format!("expected key:value, got {}", condition))
+    })?;
+    let (key, value) = (&condition[..separator], &condition[separator + 1..]);
+    let pattern = || match &condition[separator..=separator] {
+        "~" => Pattern::regex(value),
+        _ => Pattern::glob(value),
+    };
+
+    match key.to_lowercase().as_str() {
+        "style" => Ok(EventFilter::Style(pattern()?)),
+        "actor" | "name" => Ok(EventFilter::Actor(pattern()?)),
+        "effect" => Ok(EventFilter::Effect(pattern()?)),
+        "text" => Ok(EventFilter::Text(pattern()?)),
+        "tag" => Ok(EventFilter::Tag(value.trim_start_matches('\\').to_string())),
+        "layer" => {
+            let (min, max) = value.split_once('-').unwrap_or((value, value));
+            let parse_layer = |layer: &str| {
+                layer
+                    .parse()
+                    .map_err(|_| FilterError::FormatError(format!("invalid layer {}", layer)))
+            };
+            Ok(EventFilter::Layer {
+                min: parse_layer(min)?,
+                max: parse_layer(max)?,
+            })
+        }
+        "time" => {
+            let (start, end) = value.split_once('-').ok_or_else(|| {
+                FilterError::FormatError(format!("expected time:start-end, got {}", value))
+            })?;
+            Ok(EventFilter::Time {
+                start: parse_time(start)?,
+                end: parse_time(end)?,
+            })
+        }
+        "comment" => match value.to_lowercase().as_str() {
+            "yes" | "true" => Ok(EventFilter::Comment(true)),
+            "no" | "false" => Ok(EventFilter::Comment(false)),
+            _ => Err(FilterError::FormatError(format!(
+                "expected comment:yes or comment:no, got {}",
+                value
+            ))),
+        },
+        _ => Err(FilterError::FormatError(format!("unknown key {}", key))),
+    }
+}
+
+// Accepts 0:01:30, 0:01:30.50 and 1:30
+fn parse_time(time: &str) -> Result<NaiveTime, FilterError> {
+    let time = match time.matches(':').count() {
+        1 => format!("0:{}", time),
+        _ => time.to_string(),
+    };
+    Ok(NaiveTime::parse_from_str(&time, "%H:%M:%S%.f")?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::event;
+
+    #[test]
+    fn event_filter_from_query() {
+        let events = [
+            event(r"Dialogue: 0,0:00:01.00,0:00:03.00,Default,Yui,0,0,0,,Are you coming?"),
+            event(r"Dialogue: 0,0:01:40.00,0:01:43.00,Default-Alt,Mio,0,0,0,,{\i1}No."),
+            event(r"Dialogue: 2,0:01:40.00,0:01:43.00,Signs,,0,0,0,,{\pos(30,60)}Cafe"),
+            event(r"Comment: 0,0:01:40.00,0:01:43.00,Default,,0,0,0,,Check timing"),
+        ];
+        let matching = |query: &str| -> Vec<usize> {
+            let filter = EventFilter::from_str(query).unwrap();
+            (0..events.len())
+                .filter(|&i| filter.matches(&events[i]))
+                .collect()
+        };
+
+        assert_eq!(matching("style:Default*"), vec![0, 1, 3]);
+        assert_eq!(matching("style:Default* not comment:yes"), vec![0, 1]);
+        assert_eq!(
+            matching(r#"style~"^(Signs|Default)$" and layer:0"#),
+            vec![0, 3]
+        );
+        assert_eq!(matching("tag:pos or actor:Yui"), vec![0, 2]);
+        assert_eq!(
+            matching("time:1:30-1:41 and not (tag:i or layer:1-5)"),
+            vec![3]
+        );
+        assert_eq!(matching(r#"text~"\?$""#), vec![0]);
+        assert!(EventFilter::from_str("style:Default and (tag:i").is_err());
+        assert!(EventFilter::from_str("colour:red").is_err());
+    }
+}
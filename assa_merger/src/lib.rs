@@ -1,2 +1,9 @@
 pub mod alignment;
+pub mod batch;
+pub mod event_classifier;
 pub mod event_processor;
+pub mod merge;
+pub mod review;
+#[cfg(test)]
+mod test_helpers;
+pub mod tui;
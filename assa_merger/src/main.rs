@@ -1,39 +1,940 @@
-use ass_comp::alignment::align_events;
-use ass_comp::event_processor::filter_events_by_style;
-use assa_parse::assa_file::event::Event;
-use assa_parse::assa_file::AssaFile;
+use ass_comp::alignment::aligned_pair::{pin_pairs, score_pairs, AlignedPair};
+use ass_comp::alignment::alignment_plan::{AlignmentPlan, ScriptSelection};
+use ass_comp::alignment::config::{AlignmentConfig, ConfigError, ConfigPreset};
+use ass_comp::alignment::evaluation::Evaluation;
+use ass_comp::alignment::multi_alignment::MultiAlignment;
+use ass_comp::alignment::semantic_alignment::embedding_cache::EmbeddingCache;
+use ass_comp::alignment::semantic_alignment::lexical_similarity::LexicalSimilarity;
+use ass_comp::alignment::semantic_alignment::semantic_similarity::{
+    parse_model_type, SemanticModelConfig, SemanticModelError, SemanticSimilarity,
+    SimilarityBackend,
+};
+use ass_comp::alignment::sync_detection::{
+    detect_cross_lingual_sync_segments, detect_sync_segments, OffsetMap,
+};
+use ass_comp::alignment::{align_events, AlignmentMode};
+use ass_comp::batch::{
+    is_batch_path, list_files, pair_episodes, summary_table, EpisodePair, EpisodeResult,
+    EpisodeStats,
+};
+use ass_comp::event_classifier::{
+    classification_report, classify_events, classify_styles, dialogue_styles, EventKind,
+    StyleOverride,
+};
+use ass_comp::event_processor::event_filter::EventFilter;
+use ass_comp::event_processor::{
+    filter_event_indices_by_style, get_events_by_indices, get_text_of_events,
+};
+use ass_comp::merge::event_import::EventImport;
+use ass_comp::merge::leftovers::UnmatchedPolicy;
+use ass_comp::merge::multi_source::{MergeInputs, MergeSource, SourceSpec};
+use ass_comp::merge::style_mapping::{StyleMapping, StyleRule};
+use ass_comp::merge::tag_transfer::TagPolicy;
+use ass_comp::merge::translation_vote::{merge_translations, TranslationSource};
+use ass_comp::merge::{merge_dialogue, ActorPolicy, MergeConfig};
+use ass_comp::review::low_confidence_report;
+use ass_comp::tui::{self, app::App, TuiOutput};
+use assa_parse::assa_file::{event::Event, AssaFile};
+use clap::{Parser, ValueEnum};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
 
-fn main() {
-    // let original_file =
-    //     "[Final8] Mirai Nikki - 01 (BD 10-bit 1920x1080 x264 FLAC)[965F3FEB]_Subtitles01.UND.ass";
-    // let modified_file = "[FFF] Mirai Nikki - 01 [BD][1080p-FLAC][AD0D9174]_Track03.ass";
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum SimilarityKind {
+    /// Sentence embeddings, downloaded on first use unless --model-dir is set
+    Semantic,
+    /// Character n-gram cosine similarity, needs no model files
+    Lexical,
+}
 
-    let original_file: &str =
-        "[Final8] Mirai Nikki - 02 (BD 10-bit 1920x1080 x264 FLAC)[7F11AAB2]_Subtitles01.UND.ass";
-    let modified_file = "[FFF] Mirai Nikki - 02 [BD][1080p-FLAC][E2BCE249]_Track03.ass";
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Actors {
+    /// Keep the actors of the base file
+    Keep,
+    /// Copy the actors of the dialogue into base events without one
+    Fill,
+    /// Replace the actors of the base file with those of the dialogue
+    Replace,
+}
 
-    let mut original = AssaFile::from_file(original_file).expect("Failed to parse file");
-    let mut modified = AssaFile::from_file(modified_file).expect("Failed to parse file");
+impl From<Actors> for ActorPolicy {
+    fn from(actors: Actors) -> Self {
+        match actors {
+            Actors::Keep => ActorPolicy::Keep,
+            Actors::Fill => ActorPolicy::Fill,
+            Actors::Replace => ActorPolicy::Replace,
+        }
+    }
+}
 
-    original.events.sort_by(|a, b| a.start.cmp(&b.start));
-    modified.events.sort_by(|a, b| a.start.cmp(&b.start));
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Unmatched {
+    /// Keep the text of the base file
+    Keep,
+    /// Turn the lines into comments
+    Comment,
+    /// Keep the lines and set their effect to "unmatched"
+    Mark,
+}
 
-    let dialogue_styles: Vec<String> = Vec::from([
-        String::from("Default"),
-        String::from("Alternate"),
-        String::from("DefaultAlt"),
-    ]);
+impl From<Unmatched> for UnmatchedPolicy {
+    fn from(unmatched: Unmatched) -> Self {
+        match unmatched {
+            Unmatched::Keep => UnmatchedPolicy::Keep,
+            Unmatched::Comment => UnmatchedPolicy::Comment,
+            Unmatched::Mark => UnmatchedPolicy::Mark,
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(about = "Merges the dialogue of one ASS/SSA subtitle into the timings of another")]
+struct Cli {
+    /// Subtitle file with the dialogue/translations to keep. A directory or
+    /// glob pattern (quoted, 'subs/*.en.ass') merges a whole season, the
+    /// files are paired with the base files by episode number
+    #[arg(long)]
+    dialogue: String,
+    /// Another translation of the same episode, used for the lines --dialogue
+    /// has no confident match for. Can be repeated, earlier files win
+    #[arg(long, value_name = "FILE")]
+    alternative_dialogue: Vec<PathBuf>,
+    /// With --alternative-dialogue, add the translations that weren't picked
+    /// as comments next to each line
+    #[arg(long, requires = "alternative_dialogue")]
+    alternatives_as_comments: bool,
+    /// Subtitle file with the timings (and signs & songs) to keep, or a
+    /// directory or glob pattern
+    #[arg(long)]
+    base: String,
+    /// Styles of the events that are dialogue, detected per file when not set
+    #[arg(long, value_delimiter = ',')]
+    styles: Option<Vec<String>>,
+    /// Sets the kind of a style when detecting the dialogue styles, e.g.
+    /// --style-kind "Flashback=dialogue" --style-kind "OP=song"
+    #[arg(long = "style-kind", value_name = "STYLE=KIND")]
+    style_kinds: Vec<StyleOverride>,
+    /// Only align the events of both files that also match this query, e.g.
+    /// --filter 'not actor:Narrator and (layer:0 or tag:i)'. Conditions are
+    /// style, actor, effect and text (key:glob or key~regex), layer:0-1,
+    /// time:0:01:30-0:22:00, tag:pos and comment:yes/no
+    #[arg(long, value_name = "QUERY")]
+    filter: Option<EventFilter>,
+    /// Print how the styles of both files are classified and exit
+    #[arg(long)]
+    classify: bool,
+    /// Backend used for the semantic alignment stage
+    #[arg(long, value_enum, default_value_t = SimilarityKind::Semantic)]
+    similarity: SimilarityKind,
+    /// The dialogue and base files are in different languages, align on
+    /// timing and meaning only
+    #[arg(long)]
+    cross_lingual: bool,
+    /// Sentence embeddings model, e.g. all-minilm-l6-v2, all-minilm-l12-v2 or
+    /// distiluse-base-multilingual-cased. Defaults to all-minilm-l6-v2, or to
+    /// distiluse-base-multilingual-cased with --cross-lingual
+    #[arg(long)]
+    model_type: Option<String>,
+    /// Load the sentence embeddings model from a local directory
+    #[arg(long)]
+    model_dir: Option<PathBuf>,
+    /// File to store sentence embeddings in, reused when the same episode is
+    /// aligned again
+    #[arg(long)]
+    embedding_cache: Option<PathBuf>,
+    /// Threshold preset: strict, default or loose
+    #[arg(long, default_value = "default")]
+    preset: String,
+    /// TOML file with alignment thresholds, overrides the preset
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Overrides a single alignment threshold, e.g. --set merge_gain=0.15
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    config_overrides: Vec<String>,
+    /// Write the list of low-confidence pairs to this file instead of stdout
+    #[arg(long)]
+    review_output: Option<PathBuf>,
+    /// Apply a saved alignment plan instead of aligning the events
+    #[arg(long)]
+    plan: Option<PathBuf>,
+    /// With --plan, align the events again and only keep the pinned pairs of
+    /// the plan
+    #[arg(long, requires = "plan")]
+    realign: bool,
+    /// Compare the alignment with a gold alignment plan (e.g. saved with
+    /// --save-plan and corrected with --interactive) and print precision,
+    /// recall and F1 per operation. When merging a season this is a template
+    /// like --output, e.g. 'gold/{base}.json'
+    #[arg(long, value_name = "GOLD_PLAN")]
+    evaluate: Option<PathBuf>,
+    /// Save the alignment plan to this file, pinned pairs can be added by hand
+    #[arg(long)]
+    save_plan: Option<PathBuf>,
+    /// Write the merged subtitle file to this file. When merging a season this
+    /// is a template with {episode}, {dialogue} and {base} (the names of the
+    /// input files), e.g. 'merged/{base}.ass'
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Override tags of the base events kept in the merged events, `*` keeps
+    /// all of them. Defaults to positioning tags, \i, \blur and \be
+    #[arg(long, value_delimiter = ',')]
+    base_tags: Option<Vec<String>>,
+    /// Override tags of the dialogue kept in the merged events, `*` keeps all
+    /// of them. Defaults to \i, \b, \u and \s
+    #[arg(long, value_delimiter = ',')]
+    dialogue_tags: Option<Vec<String>>,
+    /// Copy the events of these styles from the dialogue file into the merged
+    /// file, e.g. signs the base file doesn't have
+    #[arg(long, value_delimiter = ',')]
+    import_styles: Vec<String>,
+    /// Copy the events of the styles classified as these kinds (sign, song)
+    /// from the dialogue file into the merged file
+    #[arg(long, value_delimiter = ',')]
+    import_kinds: Vec<EventKind>,
+    /// Take the signs and/or songs from another release instead of the base
+    /// file, e.g. --source 'signs=[GroupC] Show - 01.ass'. Can be repeated
+    #[arg(long = "source", value_name = "KINDS=FILE")]
+    sources: Vec<SourceSpec>,
+    /// Maps a dialogue style to a base style and override tags, e.g.
+    /// --style-map 'Italics=Default{\i1}' --style-map 'Top={\an8}'
+    #[arg(long = "style-map", value_name = "RULE")]
+    style_rules: Vec<StyleRule>,
+    /// Only use the --style-map rules, don't compare the dialogue styles with
+    /// the main dialogue style
+    #[arg(long)]
+    no_auto_style_map: bool,
+    /// Where the actors (name field) of the merged events come from
+    #[arg(long, value_enum, default_value_t = Actors::Fill)]
+    actors: Actors,
+    /// What happens to base dialogue lines no dialogue was aligned with
+    #[arg(long, value_enum, default_value_t = Unmatched::Keep)]
+    unmatched: Unmatched,
+    /// Don't add dialogue lines without a base line as comments
+    #[arg(long)]
+    no_unmatched_dialogue: bool,
+    /// Drop the {notes} in the dialogue and the Comment events of the
+    /// dialogue file
+    #[arg(long)]
+    no_notes: bool,
+    /// Review and correct the alignment in a terminal UI before saving
+    #[arg(long)]
+    interactive: bool,
+    #[arg(long, default_value_t = 4)]
+    lookahead: usize,
+}
 
-    let original_dialogue_events: Vec<&Event> =
-        filter_events_by_style(&original.events, &dialogue_styles, false);
+fn main() {
+    let cli = Cli::parse();
+    let config = match load_config(&cli) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+    if is_batch_path(&cli.dialogue) || is_batch_path(&cli.base) {
+        run_batch(&cli, &config);
+        return;
+    }
+    if cli.interactive && !cli.alternative_dialogue.is_empty() {
+        eprintln!("--alternative-dialogue can't be used with --interactive");
+        process::exit(1);
+    }
 
-    let modified_dialogue_events: Vec<&Event> =
-        filter_events_by_style(&modified.events, &dialogue_styles, false);
+    let original = load_file(&cli.dialogue).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+    let modified = load_file(&cli.base).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+
+    let alternative_files: Vec<AssaFile> = cli
+        .alternative_dialogue
+        .iter()
+        .map(|path| {
+            load_file(&path.to_string_lossy()).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                process::exit(1);
+            })
+        })
+        .collect();
+
+    let original_styles = select_dialogue_styles(&cli, &original, "Dialogue file");
+    let alternative_styles: Vec<Vec<String>> = cli
+        .alternative_dialogue
+        .iter()
+        .zip(&alternative_files)
+        .map(|(path, file)| select_dialogue_styles(&cli, file, &path.display().to_string()))
+        .collect();
+    let modified_styles = select_dialogue_styles(&cli, &modified, "Base file");
+    if cli.classify {
+        return;
+    }
+
+    let original_indices = select_indices(&cli, &original, &original_styles, "Dialogue file")
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+    let modified_indices = select_indices(&cli, &modified, &modified_styles, "Base file")
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        });
+    let dialogue = ScriptSelection {
+        events: &original.events,
+        indices: &original_indices,
+    };
+    let base = ScriptSelection {
+        events: &modified.events,
+        indices: &modified_indices,
+    };
+    let alternative_indices: Vec<Vec<usize>> = cli
+        .alternative_dialogue
+        .iter()
+        .zip(&alternative_files)
+        .zip(&alternative_styles)
+        .map(|((path, file), styles)| {
+            select_indices(&cli, file, styles, &path.display().to_string()).unwrap_or_else(
+                |error| {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                },
+            )
+        })
+        .collect();
+    let file_label = |path: &Path| {
+        path.file_stem()
+            .map_or(String::new(), |stem| stem.to_string_lossy().to_string())
+    };
+    // The dialogue file first, the order is the priority of the vote
+    let translation_sources: Vec<TranslationSource> = std::iter::once(TranslationSource {
+        label: file_label(Path::new(&cli.dialogue)),
+        dialogue,
+    })
+    .chain(
+        cli.alternative_dialogue
+            .iter()
+            .zip(&alternative_files)
+            .zip(&alternative_indices)
+            .map(|((path, file), indices)| TranslationSource {
+                label: file_label(path),
+                dialogue: ScriptSelection {
+                    events: &file.events,
+                    indices,
+                },
+            }),
+    )
+    .collect();
+    let original_dialogue_events = get_events_by_indices(&original.events, &original_indices);
+    let modified_dialogue_events = get_events_by_indices(&modified.events, &modified_indices);
 
     // original_dialogue_events.drain(40..original_dialogue_events.len());
     // modified_dialogue_events.drain(40..modified_dialogue_events.len());
 
-    align_events(&original_dialogue_events, &modified_dialogue_events, 4);
+    let plan = cli
+        .plan
+        .as_deref()
+        .map(|plan_path| match AlignmentPlan::load_file(plan_path) {
+            Ok(plan) => plan,
+            Err(error) => {
+                eprintln!("{}: {}", error, plan_path.display());
+                process::exit(1);
+            }
+        });
+
+    // The alternative dialogue files are always aligned
+    let semantic_similarity = match (&plan, cli.realign, cli.alternative_dialogue.is_empty()) {
+        (Some(_), false, true) => None,
+        _ => load_similarity(&cli),
+    };
+    let (aligned_pairs, offset_map) = match (&plan, cli.realign) {
+        (Some(plan), false) => match plan.apply(&dialogue, &base) {
+            Ok(aligned_pairs) => (
+                aligned_pairs,
+                detect_sync_segments(
+                    &original_dialogue_events,
+                    &modified_dialogue_events,
+                    &config,
+                ),
+            ),
+            Err(error) => {
+                eprintln!("{}", error);
+                eprintln!("Use --realign to align again and only keep the pinned pairs.");
+                process::exit(1);
+            }
+        },
+        _ => align_with_similarity(
+            &cli,
+            &config,
+            &dialogue,
+            &base,
+            plan.as_ref(),
+            semantic_similarity.as_deref(),
+        ),
+    };
+
+    if let Some(gold_path) = cli.evaluate.as_deref() {
+        match evaluate_alignment(gold_path, &aligned_pairs, &dialogue, &base) {
+            Ok(evaluation) => println!("{}", evaluation.report()),
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+        }
+    }
+
+    let merge_config = merge_config(&cli, &original, &original_styles);
+
+    let event_import =
+        select_imported_events(&cli, &original, &original_indices).map(|indices| EventImport {
+            source_file: &original,
+            indices,
+            offset_map,
+        });
+
+    let source_files: Vec<AssaFile> = cli
+        .sources
+        .iter()
+        .map(|source| {
+            load_file(&source.path.to_string_lossy()).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                process::exit(1);
+            })
+        })
+        .collect();
+    let sources: Vec<MergeSource> = cli
+        .sources
+        .iter()
+        .zip(&source_files)
+        .map(|(source, file)| MergeSource {
+            file,
+            kinds: source.kinds.clone(),
+            offset_map: source_offset_map(&cli, &config, file, &modified_dialogue_events),
+        })
+        .collect();
+    let inputs = MergeInputs {
+        base_file: &modified,
+        event_import: event_import.as_ref(),
+        sources: &sources,
+        style_overrides: &cli.style_kinds,
+    };
+
+    // The plan and merged file are saved from within the UI
+    if cli.interactive {
+        let mut app = App::new(aligned_pairs, dialogue, base, config.review_threshold);
+        let output = TuiOutput {
+            inputs,
+            merge_config: &merge_config,
+            plan_path: cli.save_plan,
+            merged_path: cli.output,
+        };
+        if let Err(error) = tui::run(&mut app, &output) {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+        return;
+    }
+
+    let review_report = low_confidence_report(
+        &aligned_pairs,
+        &original_dialogue_events,
+        &modified_dialogue_events,
+        config.review_threshold,
+    );
+    match cli.review_output.as_deref() {
+        Some(review_path) => {
+            if let Err(error) = fs::write(review_path, review_report) {
+                eprintln!("{}: {}", error, review_path.display());
+            }
+        }
+        None => println!("{}", review_report),
+    }
+
+    if let Some(plan_path) = cli.save_plan.as_deref() {
+        let plan = AlignmentPlan::from_pairs(&aligned_pairs, &dialogue, &base);
+        if let Err(error) = plan.save_file(plan_path) {
+            eprintln!("{}: {}", error, plan_path.display());
+        }
+    }
+
+    if let Some(output_path) = cli.output.as_deref() {
+        let mut merged_file = match alternative_files.is_empty() {
+            true => merge_dialogue(&modified, &dialogue, &base, &aligned_pairs, &merge_config),
+            false => {
+                let alignment = align_translations(
+                    &cli,
+                    &config,
+                    &base,
+                    &translation_sources,
+                    &aligned_pairs,
+                    semantic_similarity.as_deref(),
+                );
+                merge_translations(
+                    &modified,
+                    &base,
+                    &translation_sources,
+                    &alignment,
+                    config.review_threshold,
+                    cli.alternatives_as_comments,
+                    &merge_config,
+                )
+            }
+        };
+        let import_counts = inputs.import_events(&mut merged_file);
+        if event_import.is_some() {
+            println!(
+                "Imported {} events from the dialogue file",
+                import_counts.dialogue_file
+            );
+        }
+        for (source, count) in cli.sources.iter().zip(import_counts.sources) {
+            println!("Imported {} events from {}", count, source.path.display());
+        }
+        if let Err(error) = merged_file.save_file_as(&output_path.to_string_lossy()) {
+            eprintln!("{}: {}", error, output_path.display());
+            process::exit(1);
+        }
+    }
+}
+
+/// Merges every episode the dialogue and base files have in common
+fn run_batch(cli: &Cli, config: &AlignmentConfig) {
+    let unsupported = [
+        (cli.interactive, "--interactive"),
+        (cli.plan.is_some(), "--plan"),
+        (cli.save_plan.is_some(), "--save-plan"),
+        (cli.review_output.is_some(), "--review-output"),
+        (cli.classify, "--classify"),
+        (!cli.sources.is_empty(), "--source"),
+        (
+            !cli.alternative_dialogue.is_empty(),
+            "--alternative-dialogue",
+        ),
+    ];
+    if let Some((_, flag)) = unsupported.iter().find(|(set, _)| *set) {
+        eprintln!("{} can't be used when merging a season", flag);
+        process::exit(1);
+    }
+    let template = match &cli.output {
+        Some(template) => template.to_string_lossy().to_string(),
+        None => {
+            eprintln!("Merging a season needs an --output template, e.g. 'merged/{{base}}.ass'");
+            process::exit(1);
+        }
+    };
+
+    let (dialogue_files, base_files) = match (list_files(&cli.dialogue), list_files(&cli.base)) {
+        (Ok(dialogue_files), Ok(base_files)) => (dialogue_files, base_files),
+        (Err(error), _) | (_, Err(error)) => {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+    };
+    let (pairs, unpaired) = pair_episodes(&dialogue_files, &base_files);
+    let semantic_similarity = load_similarity(cli);
+
+    let mut evaluation = Evaluation::default();
+    let mut results: Vec<EpisodeResult> = Vec::with_capacity(pairs.len());
+    for pair in pairs {
+        println!("Episode {}", pair.episode);
+        let output_path = pair.output_path(&template);
+        // The aligner and the language model still panic on some inputs, that
+        // shouldn't cost the other episodes
+        let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+            merge_episode(
+                cli,
+                config,
+                &pair,
+                &output_path,
+                semantic_similarity.as_deref(),
+                &mut evaluation,
+            )
+        }))
+        .unwrap_or_else(|_| Err(String::from("aligner crashed")));
+        results.push(EpisodeResult { pair, outcome });
+    }
+
+    println!("{}", summary_table(&results, &unpaired));
+    if cli.evaluate.is_some() {
+        println!("All episodes\n{}", evaluation.report());
+    }
+    if results.iter().any(|result| result.outcome.is_err()) {
+        process::exit(1);
+    }
+}
+
+fn merge_episode(
+    cli: &Cli,
+    config: &AlignmentConfig,
+    pair: &EpisodePair,
+    output_path: &Path,
+    semantic_similarity: Option<&dyn SimilarityBackend>,
+    evaluation: &mut Evaluation,
+) -> Result<EpisodeStats, String> {
+    let original = load_file(&pair.dialogue.to_string_lossy())?;
+    let modified = load_file(&pair.base.to_string_lossy())?;
+    let original_styles = select_dialogue_styles(cli, &original, "Dialogue file");
+    let modified_styles = select_dialogue_styles(cli, &modified, "Base file");
+    let original_indices = select_indices(cli, &original, &original_styles, "Dialogue file")?;
+    let modified_indices = select_indices(cli, &modified, &modified_styles, "Base file")?;
+
+    let dialogue = ScriptSelection {
+        events: &original.events,
+        indices: &original_indices,
+    };
+    let base = ScriptSelection {
+        events: &modified.events,
+        indices: &modified_indices,
+    };
+    let (aligned_pairs, offset_map) =
+        align_with_similarity(cli, config, &dialogue, &base, None, semantic_similarity);
+    if let Some(template) = cli.evaluate.as_deref() {
+        let gold_path = pair.output_path(&template.to_string_lossy());
+        let episode_evaluation = evaluate_alignment(&gold_path, &aligned_pairs, &dialogue, &base)?;
+        println!("{}", episode_evaluation.report());
+        evaluation.add(&episode_evaluation);
+    }
+
+    let merge_config = merge_config(cli, &original, &original_styles);
+    let mut merged_file =
+        merge_dialogue(&modified, &dialogue, &base, &aligned_pairs, &merge_config);
+    let event_import =
+        select_imported_events(cli, &original, &original_indices).map(|indices| EventImport {
+            source_file: &original,
+            indices,
+            offset_map,
+        });
+    let inputs = MergeInputs {
+        base_file: &modified,
+        event_import: event_import.as_ref(),
+        sources: &[],
+        style_overrides: &cli.style_kinds,
+    };
+    inputs.import_events(&mut merged_file);
+    if let Some(directory) = output_path.parent() {
+        fs::create_dir_all(directory).map_err(|error| error.to_string())?;
+    }
+    merged_file
+        .save_file_as(&output_path.to_string_lossy())
+        .map_err(|error| format!("{}: {}", error, output_path.display()))?;
+
+    Ok(EpisodeStats::from_pairs(
+        &aligned_pairs,
+        config.review_threshold,
+    ))
+}
+
+/// Scores the aligned pairs against the gold alignment plan
+fn evaluate_alignment(
+    gold_path: &Path,
+    aligned_pairs: &[AlignedPair],
+    dialogue: &ScriptSelection,
+    base: &ScriptSelection,
+) -> Result<Evaluation, String> {
+    let gold_pairs = AlignmentPlan::load_file(gold_path)
+        .and_then(|plan| plan.apply(dialogue, base))
+        .map_err(|error| format!("{}: {}", error, gold_path.display()))?;
+    Ok(Evaluation::new(aligned_pairs, &gold_pairs))
+}
+
+/// Offset from the timeline of an extra source to the base file, found by
+/// matching the dialogue of both
+fn source_offset_map(
+    cli: &Cli,
+    config: &AlignmentConfig,
+    source_file: &AssaFile,
+    base_dialogue_events: &[&Event],
+) -> OffsetMap {
+    let kinds = classify_events(source_file);
+    let classifications = classify_styles(&source_file.events, &kinds, &cli.style_kinds);
+    // Without dialogue there's nothing to match, the timing is kept
+    let source_indices = match select_indices(
+        cli,
+        source_file,
+        &dialogue_styles(&classifications),
+        "Source file",
+    ) {
+        Ok(source_indices) => source_indices,
+        Err(_) => return OffsetMap { segments: vec![] },
+    };
+    let source_dialogue_events = get_events_by_indices(&source_file.events, &source_indices);
+    detect_sync_segments(&source_dialogue_events, base_dialogue_events, config)
+}
+
+/// Reads the file with its events sorted by start time
+fn load_file(path: &str) -> Result<AssaFile, String> {
+    let mut file = AssaFile::from_file(path).map_err(|error| format!("{}: {}", error, path))?;
+    file.events.sort_by_key(|event| event.start);
+    Ok(file)
+}
+
+/// Events of the dialogue styles that pass the --filter, there has to be at
+/// least one to align
+fn select_indices(
+    cli: &Cli,
+    file: &AssaFile,
+    styles: &[String],
+    title: &str,
+) -> Result<Vec<usize>, String> {
+    if styles.is_empty() {
+        return Err(format!(
+            "{}: no dialogue styles found, set them with --styles or --style-kind",
+            title
+        ));
+    }
+    let mut indices = filter_event_indices_by_style(&file.events, styles, false);
+    if let Some(filter) = &cli.filter {
+        indices.retain(|&i| filter.matches(&file.events[i]));
+        if indices.is_empty() {
+            return Err(format!("{}: filter matched no events", title));
+        }
+    }
+    match indices.is_empty() {
+        true => Err(format!("{}: no dialogue lines", title)),
+        false => Ok(indices),
+    }
+}
+
+fn merge_config(cli: &Cli, dialogue_file: &AssaFile, dialogue_styles: &[String]) -> MergeConfig {
+    let default_policy = TagPolicy::default();
+    MergeConfig {
+        tag_policy: TagPolicy {
+            base_tags: cli.base_tags.clone().unwrap_or(default_policy.base_tags),
+            dialogue_tags: cli
+                .dialogue_tags
+                .clone()
+                .unwrap_or(default_policy.dialogue_tags),
+        },
+        style_mapping: match cli.no_auto_style_map {
+            true => StyleMapping::default(),
+            false => StyleMapping::automatic(dialogue_file, dialogue_styles),
+        }
+        .with_rules(&cli.style_rules),
+        actor_policy: cli.actors.into(),
+        unmatched_policy: cli.unmatched.into(),
+        insert_unmatched_dialogue: !cli.no_unmatched_dialogue,
+        carry_notes: !cli.no_notes,
+    }
+}
+
+fn alignment_mode(cli: &Cli) -> AlignmentMode {
+    match cli.cross_lingual {
+        true => AlignmentMode::CrossLingual,
+        false => AlignmentMode::Monolingual,
+    }
+}
+
+/// The sentence embeddings model, None for the lexical backend which is built
+/// from the text of each episode
+fn load_similarity(cli: &Cli) -> Option<Box<dyn SimilarityBackend>> {
+    let mode = alignment_mode(cli);
+    let model_type = cli.model_type.clone().unwrap_or(String::from(match mode {
+        AlignmentMode::Monolingual => "all-minilm-l6-v2",
+        AlignmentMode::CrossLingual => "distiluse-base-multilingual-cased",
+    }));
+    if let (AlignmentMode::CrossLingual, SimilarityKind::Lexical) = (mode, cli.similarity) {
+        eprintln!("Cross-lingual alignment needs a multilingual semantic model.");
+        process::exit(1);
+    }
+
+    match cli.similarity {
+        SimilarityKind::Semantic => {
+            match load_semantic_similarity(&model_type, cli.model_dir.clone()) {
+                Ok(semantic_similarity) => Some(Box::new(semantic_similarity)),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    eprintln!("Use --similarity lexical to align without a language model.");
+                    process::exit(1);
+                }
+            }
+        }
+        SimilarityKind::Lexical => None,
+    }
+}
+
+fn align_with_similarity(
+    cli: &Cli,
+    config: &AlignmentConfig,
+    dialogue: &ScriptSelection,
+    base: &ScriptSelection,
+    plan: Option<&AlignmentPlan>,
+    semantic_model: Option<&dyn SimilarityBackend>,
+) -> (Vec<AlignedPair>, OffsetMap) {
+    let original_dialogue_events = get_events_by_indices(dialogue.events, dialogue.indices);
+    let modified_dialogue_events = get_events_by_indices(base.events, base.indices);
+    let mode = alignment_mode(cli);
+
+    let lexical_similarity;
+    let semantic_similarity: &dyn SimilarityBackend = match semantic_model {
+        Some(semantic_model) => semantic_model,
+        None => {
+            lexical_similarity = LexicalSimilarity::default().with_corpus(
+                &original_dialogue_events
+                    .iter()
+                    .chain(modified_dialogue_events.iter())
+                    .map(|event| event.text.as_str())
+                    .collect::<Vec<&str>>(),
+            );
+            &lexical_similarity
+        }
+    };
+
+    let embedding_cache = EmbeddingCache::new(semantic_similarity);
+    if let Some(cache_path) = cli.embedding_cache.as_deref().filter(|path| path.exists()) {
+        match embedding_cache.load_file(cache_path) {
+            Ok(true) => println!("Loaded {} cached embeddings", embedding_cache.len()),
+            Ok(false) => println!("Ignoring embedding cache of a different model"),
+            Err(error) => eprintln!("{}: {}", error, cache_path.display()),
+        }
+    }
+    embedding_cache.preload(&get_text_of_events(&original_dialogue_events));
+    embedding_cache.preload(&get_text_of_events(&modified_dialogue_events));
+
+    let offset_map = match mode {
+        AlignmentMode::Monolingual => {
+            detect_sync_segments(&original_dialogue_events, &modified_dialogue_events, config)
+        }
+        AlignmentMode::CrossLingual => detect_cross_lingual_sync_segments(
+            &original_dialogue_events,
+            &modified_dialogue_events,
+            &embedding_cache,
+            config,
+        ),
+    };
+    println!("{}", offset_map);
+
+    let mut aligned_pairs = align_events(
+        &original_dialogue_events,
+        &modified_dialogue_events,
+        &offset_map,
+        &embedding_cache,
+        mode,
+        config,
+        cli.lookahead,
+    );
+
+    // Manual corrections of an earlier run take precedence over the aligner
+    if let Some(plan) = plan {
+        let (pinned_pairs, errors) = plan.pinned_pairs(dialogue, base);
+        for error in errors {
+            eprintln!("Dropping pinned pair: {}", error);
+        }
+        aligned_pairs = pin_pairs(aligned_pairs, pinned_pairs);
+        score_pairs(
+            &mut aligned_pairs,
+            &original_dialogue_events,
+            &modified_dialogue_events,
+            &offset_map,
+            &embedding_cache,
+            mode,
+            config,
+        );
+    }
+
+    if let Some(cache_path) = cli.embedding_cache.as_deref() {
+        if let Err(error) = embedding_cache.save_file(cache_path) {
+            eprintln!("{}: {}", error, cache_path.display());
+        }
+    }
+    (aligned_pairs, offset_map)
+}
+
+/// Aligns the other translations with the base, the pairs of the first source
+/// are already aligned
+fn align_translations(
+    cli: &Cli,
+    config: &AlignmentConfig,
+    base: &ScriptSelection,
+    sources: &[TranslationSource],
+    aligned_pairs: &[AlignedPair],
+    semantic_similarity: Option<&dyn SimilarityBackend>,
+) -> MultiAlignment {
+    let mut source_pairs = vec![aligned_pairs.to_vec()];
+    for source in &sources[1..] {
+        let (pairs, _) = align_with_similarity(
+            cli,
+            config,
+            &source.dialogue,
+            base,
+            None,
+            semantic_similarity,
+        );
+        source_pairs.push(pairs);
+    }
+    MultiAlignment::new(base.indices.len(), source_pairs)
+}
+
+/// Events of the dialogue file with an --import-styles style or a style of an
+/// --import-kinds kind, apart from the dialogue that is merged
+fn select_imported_events(
+    cli: &Cli,
+    file: &AssaFile,
+    dialogue_indices: &[usize],
+) -> Option<Vec<usize>> {
+    if cli.import_styles.is_empty() && cli.import_kinds.is_empty() {
+        return None;
+    }
+
+    let kinds = classify_events(file);
+    let classifications = classify_styles(&file.events, &kinds, &cli.style_kinds);
+    let indices = file
+        .events
+        .iter()
+        .enumerate()
+        .filter(|(i, event)| {
+            !event.comment
+                && !dialogue_indices.contains(i)
+                && (cli.import_styles.contains(&event.style)
+                    || classifications.iter().any(|classification| {
+                        classification.style == event.style
+                            && cli.import_kinds.contains(&classification.kind)
+                    }))
+        })
+        .map(|(i, _)| i)
+        .collect();
+    Some(indices)
+}
+
+fn load_config(cli: &Cli) -> Result<AlignmentConfig, ConfigError> {
+    let preset = ConfigPreset::from_str(&cli.preset)?;
+    let mut config = match &cli.config {
+        Some(config_path) => AlignmentConfig::from_file(config_path, preset)?,
+        None => AlignmentConfig::preset(preset),
+    };
+    for config_override in &cli.config_overrides {
+        config = config.with_override(config_override)?;
+    }
+    Ok(config)
+}
+
+/// The --styles of the user, or the styles classified as dialogue
+fn select_dialogue_styles(cli: &Cli, file: &AssaFile, title: &str) -> Vec<String> {
+    if let (Some(styles), false) = (&cli.styles, cli.classify) {
+        return styles.clone();
+    }
+
+    let kinds = classify_events(file);
+    let classifications = classify_styles(&file.events, &kinds, &cli.style_kinds);
+    if cli.classify {
+        println!("{}", classification_report(title, &classifications));
+    }
+    dialogue_styles(&classifications)
+}
+
+fn load_semantic_similarity(
+    model_type: &str,
+    model_dir: Option<PathBuf>,
+) -> Result<SemanticSimilarity, SemanticModelError> {
+    SemanticSimilarity::from_config(&SemanticModelConfig {
+        model_type: parse_model_type(model_type)?,
+        local_path: model_dir,
+    })
 }
 
 // pub fn main() {
@@ -0,0 +1,240 @@
+// Builds the merged script from the aligned pairs
+//
+// The base file is kept as is (script info, styles, signs & songs), only the
+// text of its dialogue events is replaced by the matching dialogue.
+
+use assa_parse::assa_file::{event::Event, AssaFile};
+
+use crate::alignment::aligned_pair::AlignedPair;
+use crate::alignment::alignment_plan::ScriptSelection;
+
+pub mod event_import;
+pub mod leftovers;
+pub mod multi_source;
+pub mod style_mapping;
+pub mod tag_transfer;
+pub mod text_distribution;
+pub mod text_joining;
+pub mod translation_vote;
+
+use leftovers::{
+    append_notes, apply_unmatched_policy, carry_comment_events, insert_unmatched_dialogue,
+    take_notes, TimeEstimate, UnmatchedPolicy,
+};
+use style_mapping::StyleMapping;
+use tag_transfer::{transfer_tags, TagPolicy};
+use text_distribution::{distribute_text, UNSPLIT_EFFECT};
+use text_joining::{join_event_texts, JoinSeparator};
+
+/// What happens to the actor (name field) of the merged events
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ActorPolicy {
+    /// Only keep the actors of the base file
+    Keep,
+    /// Base events without an actor get the actors of their dialogue
+    #[default]
+    Fill,
+    /// The actors of the dialogue replace the actors of the base file
+    Replace,
+}
+
+#[derive(Debug, Clone)]
+pub struct MergeConfig {
+    pub tag_policy: TagPolicy,
+    pub style_mapping: StyleMapping,
+    pub actor_policy: ActorPolicy,
+    pub unmatched_policy: UnmatchedPolicy,
+    /// Add dialogue lines without a base line as comments
+    pub insert_unmatched_dialogue: bool,
+    /// Keep the {notes} in the dialogue and the Comment events of the
+    /// dialogue file
+    pub carry_notes: bool,
+}
+
+impl Default for MergeConfig {
+    fn default() -> Self {
+        Self {
+            tag_policy: TagPolicy::default(),
+            style_mapping: StyleMapping::default(),
+            actor_policy: ActorPolicy::default(),
+            unmatched_policy: UnmatchedPolicy::default(),
+            insert_unmatched_dialogue: true,
+            carry_notes: true,
+        }
+    }
+}
+
+/// Copies the dialogue of every matched pair into a copy of `base_file`, of
+/// which `base` has to be the selection of dialogue events.
+pub fn merge_dialogue(
+    base_file: &AssaFile,
+    dialogue: &ScriptSelection,
+    base: &ScriptSelection,
+    pairs: &[AlignedPair],
+    config: &MergeConfig,
+) -> AssaFile {
+    let mut merged_file = base_file.clone();
+    merge_pairs(&mut merged_file, base_file, dialogue, base, pairs, config);
+
+    let time_estimate = TimeEstimate::from_pairs(base_file, dialogue, base, pairs);
+    apply_unmatched_policy(&mut merged_file, base, pairs, config.unmatched_policy);
+    // Both add events, the indices of the base events aren't valid after this
+    if config.insert_unmatched_dialogue {
+        insert_unmatched_dialogue(&mut merged_file, dialogue, pairs, &time_estimate);
+    }
+    if config.carry_notes {
+        carry_comment_events(&mut merged_file, dialogue, &time_estimate);
+    }
+
+    merged_file
+}
+
+/// Replaces the text of the base events of every matched pair with the
+/// dialogue, `merged_file` has to have the events of `base_file`
+pub fn merge_pairs(
+    merged_file: &mut AssaFile,
+    base_file: &AssaFile,
+    dialogue: &ScriptSelection,
+    base: &ScriptSelection,
+    pairs: &[AlignedPair],
+    config: &MergeConfig,
+) {
+    let mut tag_policy = config.tag_policy.clone();
+    tag_policy
+        .dialogue_tags
+        .extend(config.style_mapping.tag_names());
+
+    for pair in pairs.iter().filter(|pair| pair.is_matched()) {
+        let dialogue_events: Vec<Event> = pair
+            .original_indices
+            .iter()
+            .map(|&i| {
+                config
+                    .style_mapping
+                    .map_event(&dialogue.events[dialogue.indices[i]])
+            })
+            .collect();
+        let (dialogue_text, notes) = take_notes(&join_event_texts(
+            &dialogue_events.iter().collect::<Vec<&Event>>(),
+            JoinSeparator::Space,
+        ));
+        let base_style = dialogue_events
+            .first()
+            .and_then(|event| config.style_mapping.rule(&event.style))
+            .and_then(|rule| rule.base_style.as_ref())
+            .filter(|&base_style| {
+                base_file
+                    .styles
+                    .iter()
+                    .any(|style| style.name == *base_style)
+            });
+        let actor = dialogue_actor(&dialogue_events);
+        let mut base_indices: Vec<usize> = pair
+            .modified_indices
+            .iter()
+            .map(|&i| base.indices[i])
+            .collect();
+        let base_events: Vec<&Event> = base_indices.iter().map(|&i| &base_file.events[i]).collect();
+
+        let mut distributed_text = distribute_text(&dialogue_text, &base_events);
+        if config.carry_notes {
+            append_notes(&mut distributed_text.parts, &notes);
+        }
+        // The first event shows the whole line for the time of all of them,
+        // the others stay in the script as comments
+        if distributed_text.fallback {
+            let last_end = base_events.iter().map(|event| event.end).max();
+            for (position, &i) in base_indices.iter().enumerate() {
+                let event = &mut merged_file.events[i];
+                event.effect = String::from(UNSPLIT_EFFECT);
+                match position {
+                    0 => event.end = last_end.unwrap_or(event.end),
+                    _ => event.comment = true,
+                }
+            }
+            base_indices.truncate(1);
+        }
+        for (&i, text) in base_indices.iter().zip(distributed_text.parts) {
+            let event = &mut merged_file.events[i];
+            event.text = transfer_tags(&base_file.events[i].text, &text, &tag_policy);
+            if let Some(base_style) = base_style {
+                event.style = base_style.clone();
+            }
+            match config.actor_policy {
+                ActorPolicy::Fill if event.name.trim().is_empty() => event.name = actor.clone(),
+                ActorPolicy::Replace if !actor.is_empty() => event.name = actor.clone(),
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Actors of the events in order, without repeats, "Mio, Ritsu" for lines of
+/// two speakers merged into one
+fn dialogue_actor(events: &[Event]) -> String {
+    let mut actors: Vec<&str> = Vec::new();
+    for event in events {
+        let actor = event.name.trim();
+        if !actor.is_empty() && !actors.contains(&actor) {
+            actors.push(actor);
+        }
+    }
+    actors.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alignment::AlignmentAction;
+    use crate::test_helpers::dialogue;
+
+    #[test]
+    fn merge_pairs_keeps_unsplit_lines_whole() {
+        let dialogue_events = vec![dialogue(1.0, 3.0, "No way, really")];
+        let base_file = AssaFile {
+            events: vec![
+                dialogue(1.0, 2.0, "Impossible."),
+                dialogue(2.0, 3.0, "Truly?"),
+                dialogue(3.0, 4.0, "Seriously?"),
+            ],
+            ..AssaFile::default()
+        };
+        let dialogue = ScriptSelection {
+            events: &dialogue_events,
+            indices: &[0],
+        };
+        let base = ScriptSelection {
+            events: &base_file.events,
+            indices: &[0, 1, 2],
+        };
+        let pairs = [AlignedPair::new(
+            vec![0],
+            vec![0, 1, 2],
+            AlignmentAction::Split,
+        )];
+        let mut merged_file = base_file.clone();
+
+        merge_pairs(
+            &mut merged_file,
+            &base_file,
+            &dialogue,
+            &base,
+            &pairs,
+            &MergeConfig::default(),
+        );
+        let events: Vec<(bool, &str, &str)> = merged_file
+            .events
+            .iter()
+            .map(|event| (event.comment, event.text.as_str(), event.effect.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (false, "No way, really", UNSPLIT_EFFECT),
+                (true, "Truly?", UNSPLIT_EFFECT),
+                (true, "Seriously?", UNSPLIT_EFFECT),
+            ]
+        );
+        assert_eq!(merged_file.events[0].end, base_file.events[2].end);
+    }
+}
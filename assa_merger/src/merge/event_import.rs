@@ -0,0 +1,140 @@
+// Copies signs and songs of the dialogue file into the merged file
+//
+// Sometimes the dialogue group typeset signs the base group didn't, or
+// translated lyrics the base left untranslated. The selected events are moved
+// onto the timeline of the base file with the detected offset, and the styles
+// they use are copied along, see StyleMerge.
+
+use assa_parse::assa_file::{event::Event, style_merge::used_styles, AssaFile};
+use chrono::{Duration, NaiveTime};
+
+use crate::alignment::sync_detection::OffsetMap;
+
+/// Events closer than this to an identical base event are already in the base
+const DUPLICATE_TOLERANCE_MS: i64 = 50;
+/// Added to the names of copied styles that conflict with a base style
+const IMPORTED_STYLE_SUFFIX: &str = " (import)";
+
+/// Events of the dialogue file to add to the merged file
+pub struct EventImport<'a> {
+    pub source_file: &'a AssaFile,
+    pub indices: Vec<usize>,
+    /// Offset from the timeline of the dialogue file to the base file
+    pub offset_map: OffsetMap,
+}
+
+impl EventImport<'_> {
+    /// Adds the events to `merged_file`, returns the number of events added
+    pub fn apply(&self, merged_file: &mut AssaFile) -> usize {
+        let mut events: Vec<Event> = Vec::new();
+        for &i in &self.indices {
+            let source_event = &self.source_file.events[i];
+            let offset = self.offset_map.offset_at(source_event.start);
+            let mut event = source_event.clone();
+            event.start = shift_time(event.start, offset);
+            event.end = shift_time(event.end, offset);
+            if !merged_file
+                .events
+                .iter()
+                .any(|base_event| is_duplicate(base_event, &event))
+            {
+                events.push(event);
+            }
+        }
+
+        let style_merge = merged_file.merge_styles(
+            self.source_file,
+            &used_styles(&events),
+            IMPORTED_STYLE_SUFFIX,
+        );
+        let imported_count = events.len();
+        for mut event in events {
+            style_merge.apply(&mut event);
+            merged_file.events.push(event);
+        }
+
+        merged_file.events.sort_by_key(|event| event.start);
+        imported_count
+    }
+}
+
+pub(crate) fn shift_time(time: NaiveTime, offset: Duration) -> NaiveTime {
+    match time.signed_duration_since(NaiveTime::MIN) + offset < Duration::zero() {
+        true => NaiveTime::MIN,
+        false => time + offset,
+    }
+}
+
+pub(crate) fn is_duplicate(base_event: &Event, event: &Event) -> bool {
+    (base_event.start - event.start).num_milliseconds().abs() <= DUPLICATE_TOLERANCE_MS
+        && (base_event.end - event.end).num_milliseconds().abs() <= DUPLICATE_TOLERANCE_MS
+        && base_event.plain_text().trim() == event.plain_text().trim()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alignment::sync_detection::SyncSegment;
+    use crate::test_helpers::event;
+    use assa_parse::assa_file::style::Style;
+
+    fn style(name: &str, fontsize: u8) -> Style {
+        Style {
+            name: name.to_string(),
+            fontsize,
+            ..Style::default()
+        }
+    }
+
+    #[test]
+    fn apply_retimes_events_and_renames_styles() {
+        let source_file = AssaFile {
+            styles: vec![style("Sign", 40), style("OP", 30)],
+            events: [
+                "Dialogue: 0,0:00:10.00,0:00:12.00,Sign,,0,0,0,,{\\pos(100,100)}Bakery",
+                "Dialogue: 0,0:00:20.00,0:00:22.00,OP,,0,0,0,,The wind is blowing",
+                "Dialogue: 0,0:00:30.00,0:00:32.00,Sign,,0,0,0,,Station",
+            ]
+            .iter()
+            .map(|line| event(line))
+            .collect(),
+            ..AssaFile::default()
+        };
+        let mut merged_file = AssaFile {
+            styles: vec![style("Sign", 50), style("OP", 30)],
+            events: vec![event(
+                "Dialogue: 0,0:00:31.00,0:00:33.00,Sign,,0,0,0,,{\\an8}Station",
+            )],
+            ..AssaFile::default()
+        };
+        let time = |time: &str| NaiveTime::parse_from_str(time, "%H:%M:%S%.f").unwrap();
+        let event_import = EventImport {
+            source_file: &source_file,
+            indices: vec![0, 1, 2],
+            offset_map: OffsetMap {
+                segments: vec![SyncSegment {
+                    start: time("0:00:00"),
+                    end: time("0:01:00"),
+                    offset: Duration::seconds(1),
+                    anchor_count: 3,
+                }],
+            },
+        };
+
+        assert_eq!(event_import.apply(&mut merged_file), 2);
+        let events: Vec<(NaiveTime, &str)> = merged_file
+            .events
+            .iter()
+            .map(|event| (event.start, event.style.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (time("0:00:11"), "Sign (import)"),
+                (time("0:00:21"), "OP"),
+                (time("0:00:31"), "Sign"),
+            ]
+        );
+        assert_eq!(merged_file.styles.len(), 3);
+    }
+}
@@ -0,0 +1,233 @@
+// Events and notes the aligned pairs don't cover
+//
+// Base lines without dialogue are kept, commented out or marked, dialogue
+// lines without a base line are added as comments so nothing the dialogue
+// group wrote gets lost. Translator notes ({TL note: ...} blocks and Comment
+// events of the dialogue file) are carried over as well.
+
+use assa_parse::assa_file::{
+    event::Event,
+    override_tags::{parse_text, segments_to_string, TextSegment},
+    AssaFile,
+};
+use chrono::{Duration, NaiveTime};
+
+use super::event_import::{is_duplicate, shift_time};
+use crate::alignment::aligned_pair::AlignedPair;
+use crate::alignment::alignment_plan::ScriptSelection;
+
+/// Effect set on events without a counterpart in the other file
+pub const UNMATCHED_EFFECT: &str = "unmatched";
+
+/// What happens to base dialogue lines no dialogue was aligned with
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum UnmatchedPolicy {
+    /// Keep the line with the text of the base file
+    #[default]
+    Keep,
+    /// Turn the line into a comment
+    Comment,
+    /// Keep the line and set its effect to UNMATCHED_EFFECT
+    Mark,
+}
+
+pub fn apply_unmatched_policy(
+    merged_file: &mut AssaFile,
+    base: &ScriptSelection,
+    pairs: &[AlignedPair],
+    policy: UnmatchedPolicy,
+) {
+    let unmatched_indices = pairs
+        .iter()
+        .filter(|pair| pair.original_indices.is_empty())
+        .flat_map(|pair| pair.modified_indices.iter().map(|&i| base.indices[i]));
+    for i in unmatched_indices {
+        let event = &mut merged_file.events[i];
+        match policy {
+            UnmatchedPolicy::Keep => (),
+            UnmatchedPolicy::Comment => event.comment = true,
+            UnmatchedPolicy::Mark => event.effect = String::from(UNMATCHED_EFFECT),
+        }
+    }
+}
+
+/// Time to add to a dialogue event to get its time in the base file, taken
+/// from the matched pairs
+pub struct TimeEstimate {
+    /// Start of the first dialogue event of every matched pair with the
+    /// offset to its first base event
+    anchors: Vec<(NaiveTime, Duration)>,
+}
+
+impl TimeEstimate {
+    pub fn from_pairs(
+        base_file: &AssaFile,
+        dialogue: &ScriptSelection,
+        base: &ScriptSelection,
+        pairs: &[AlignedPair],
+    ) -> Self {
+        let mut anchors: Vec<(NaiveTime, Duration)> = pairs
+            .iter()
+            .filter(|pair| pair.is_matched())
+            .map(|pair| {
+                let dialogue_event = &dialogue.events[dialogue.indices[pair.original_indices[0]]];
+                let base_event = &base_file.events[base.indices[pair.modified_indices[0]]];
+                (
+                    dialogue_event.start,
+                    base_event.start - dialogue_event.start,
+                )
+            })
+            .collect();
+        anchors.sort_by_key(|&(start, _)| start);
+        Self { anchors }
+    }
+
+    /// Offset of the matched pair closest in time
+    pub fn offset_at(&self, time: NaiveTime) -> Duration {
+        self.anchors
+            .iter()
+            .min_by_key(|(start, _)| (*start - time).num_milliseconds().abs())
+            .map_or(Duration::zero(), |&(_, offset)| offset)
+    }
+
+    pub fn retime(&self, event: &mut Event) {
+        let offset = self.offset_at(event.start);
+        event.start = shift_time(event.start, offset);
+        event.end = shift_time(event.end, offset);
+    }
+}
+
+/// Adds the dialogue lines no base line was aligned with to the merged file
+/// as comments, returns the number of lines added
+pub fn insert_unmatched_dialogue(
+    merged_file: &mut AssaFile,
+    dialogue: &ScriptSelection,
+    pairs: &[AlignedPair],
+    time_estimate: &TimeEstimate,
+) -> usize {
+    let mut inserted_count = 0;
+    let unmatched_indices = pairs
+        .iter()
+        .filter(|pair| pair.modified_indices.is_empty())
+        .flat_map(|pair| pair.original_indices.iter().map(|&i| dialogue.indices[i]));
+    for i in unmatched_indices {
+        let mut event = dialogue.events[i].clone();
+        time_estimate.retime(&mut event);
+        event.comment = true;
+        event.effect = String::from(UNMATCHED_EFFECT);
+        merged_file.events.push(event);
+        inserted_count += 1;
+    }
+    merged_file.events.sort_by_key(|event| event.start);
+    inserted_count
+}
+
+/// Adds the Comment events of the dialogue file the merged file doesn't have
+/// yet, returns the number of events added
+pub fn carry_comment_events(
+    merged_file: &mut AssaFile,
+    dialogue: &ScriptSelection,
+    time_estimate: &TimeEstimate,
+) -> usize {
+    let mut events: Vec<Event> = Vec::new();
+    for source_event in dialogue.events.iter().filter(|event| event.comment) {
+        let mut event = source_event.clone();
+        time_estimate.retime(&mut event);
+        if !merged_file
+            .events
+            .iter()
+            .any(|merged_event| is_duplicate(merged_event, &event))
+        {
+            events.push(event);
+        }
+    }
+
+    let carried_count = events.len();
+    merged_file.events.extend(events);
+    merged_file.events.sort_by_key(|event| event.start);
+    carried_count
+}
+
+/// Splits the {notes} off the text, returns the text without them and the
+/// note blocks
+pub fn take_notes(text: &str) -> (String, String) {
+    let (notes, segments): (Vec<TextSegment>, Vec<TextSegment>) = parse_text(text)
+        .into_iter()
+        .partition(|segment| matches!(segment, TextSegment::Comment(_)));
+    (segments_to_string(&segments), segments_to_string(&notes))
+}
+
+/// Puts the notes back after the text of the last part that has text
+pub fn append_notes(parts: &mut [String], notes: &str) {
+    if let Some(part) = parts.iter_mut().rev().find(|part| !part.is_empty()) {
+        part.push_str(notes);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alignment::AlignmentAction;
+    use crate::test_helpers::event;
+
+    #[test]
+    fn leftovers_are_kept_as_comments() {
+        let dialogue_events = [
+            event("Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,Hi."),
+            event("Comment: 0,0:00:02.00,0:00:03.00,Default,,0,0,0,,TL: this is a pun"),
+            event("Dialogue: 0,0:00:05.00,0:00:06.00,Default,,0,0,0,,Run!"),
+        ];
+        let mut base_file = AssaFile {
+            events: vec![
+                event("Dialogue: 0,0:00:11.00,0:00:12.00,Default,,0,0,0,,Hello."),
+                event("Dialogue: 0,0:00:13.00,0:00:14.00,Default,,0,0,0,,Hm?"),
+            ],
+            ..AssaFile::default()
+        };
+        let dialogue = ScriptSelection {
+            events: &dialogue_events,
+            indices: &[0, 2],
+        };
+        let base = ScriptSelection {
+            events: &base_file.events.clone(),
+            indices: &[0, 1],
+        };
+        let pairs = [
+            AlignedPair::new(vec![0], vec![0], AlignmentAction::None),
+            AlignedPair::new(vec![], vec![1], AlignmentAction::None),
+            AlignedPair::new(vec![1], vec![], AlignmentAction::None),
+        ];
+
+        let time_estimate = TimeEstimate::from_pairs(&base_file, &dialogue, &base, &pairs);
+        apply_unmatched_policy(&mut base_file, &base, &pairs, UnmatchedPolicy::Mark);
+        assert_eq!(
+            insert_unmatched_dialogue(&mut base_file, &dialogue, &pairs, &time_estimate),
+            1
+        );
+        assert_eq!(
+            carry_comment_events(&mut base_file, &dialogue, &time_estimate),
+            1
+        );
+        let events: Vec<(bool, &str, &str)> = base_file
+            .events
+            .iter()
+            .map(|event| (event.comment, event.effect.as_str(), event.text.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (false, "", "Hello."),
+                (true, "", "TL: this is a pun"),
+                (false, UNMATCHED_EFFECT, "Hm?"),
+                (true, UNMATCHED_EFFECT, "Run!"),
+            ]
+        );
+        assert_eq!(base_file.events[3].start.to_string(), "00:00:15");
+
+        let (text, notes) = take_notes(r"{\i1}Nice pun{TL: it's a pun in Japanese}, right?");
+        assert_eq!(text, r"{\i1}Nice pun, right?");
+        let mut parts = vec![String::from("Nice pun,"), String::from("right?")];
+        append_notes(&mut parts, &notes);
+        assert_eq!(parts[1], "right?{TL: it's a pun in Japanese}");
+    }
+}
@@ -0,0 +1,255 @@
+// Merging the events of more than two releases
+//
+// The base file is the timing reference and the dialogue file provides the
+// dialogue, any number of further sources can provide signs or songs, e.g.
+//   --source signs='[GroupC] Show - 01.ass' --source songs='[GroupD] Show - 01.ass'
+// The events of those kinds in the base file are replaced by the events of
+// the sources, which are moved onto the timeline of the base file and bring
+// their styles along (see EventImport). MergeInputs holds the files by role.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use assa_parse::assa_file::AssaFile;
+use thiserror::Error;
+
+use super::event_import::EventImport;
+use crate::alignment::sync_detection::OffsetMap;
+use crate::event_classifier::{
+    classify_events, classify_styles, styles_of_kinds, ClassificationError, EventKind,
+    StyleOverride,
+};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum SourceError {
+    #[error("expected kinds=file, got {0}")]
+    FormatError(String),
+    #[error("invalid event kind")]
+    KindError(ClassificationError),
+    #[error("{0} events can't come from an extra source, only signs and songs")]
+    UnsupportedKind(EventKind),
+}
+
+impl From<ClassificationError> for SourceError {
+    fn from(error: ClassificationError) -> Self {
+        SourceError::KindError(error)
+    }
+}
+
+/// An extra input file and the kinds of events it contributes, parsed from
+/// `signs=file.ass` or `signs,songs=file.ass`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSpec {
+    pub kinds: Vec<EventKind>,
+    pub path: PathBuf,
+}
+
+impl FromStr for SourceSpec {
+    type Err = SourceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kinds, path) = s
+            .split_once('=')
+            .filter(|(_, path)| !path.is_empty())
+            .ok_or_else(|| SourceError::FormatError(s.to_string()))?;
+        let kinds = kinds
+            .split(',')
+            .map(EventKind::from_str)
+            .collect::<Result<Vec<EventKind>, ClassificationError>>()?;
+        if let Some(&kind) = kinds
+            .iter()
+            .find(|&&kind| !matches!(kind, EventKind::Sign | EventKind::Song))
+        {
+            return Err(SourceError::UnsupportedKind(kind));
+        }
+
+        Ok(SourceSpec {
+            kinds,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+/// A loaded extra source
+pub struct MergeSource<'a> {
+    pub file: &'a AssaFile,
+    pub kinds: Vec<EventKind>,
+    /// Offset from the timeline of this file to the base file
+    pub offset_map: OffsetMap,
+}
+
+impl MergeSource<'_> {
+    /// The events of the source's kinds, comments aren't included
+    pub fn event_import(&self, overrides: &[StyleOverride]) -> EventImport<'_> {
+        let classifications =
+            classify_styles(&self.file.events, &classify_events(self.file), overrides);
+        let styles = styles_of_kinds(&classifications, &self.kinds);
+        EventImport {
+            source_file: self.file,
+            indices: (0..self.file.events.len())
+                .filter(|&i| {
+                    let event = &self.file.events[i];
+                    !event.comment && styles.contains(&event.style)
+                })
+                .collect(),
+            offset_map: self.offset_map.clone(),
+        }
+    }
+}
+
+/// The input files of a merge by role. The base file is the timing reference
+/// whose dialogue is replaced, the dialogue file can bring events besides its
+/// dialogue and the sources replace the signs or songs of the base.
+pub struct MergeInputs<'a> {
+    pub base_file: &'a AssaFile,
+    pub event_import: Option<&'a EventImport<'a>>,
+    pub sources: &'a [MergeSource<'a>],
+    pub style_overrides: &'a [StyleOverride],
+}
+
+/// Number of events added by each input
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImportCounts {
+    pub dialogue_file: usize,
+    pub sources: Vec<usize>,
+}
+
+impl MergeInputs<'_> {
+    /// Adds the events of the dialogue file and the sources to `merged_file`,
+    /// which has the merged dialogue of the base file
+    pub fn import_events(&self, merged_file: &mut AssaFile) -> ImportCounts {
+        // Before anything is imported, so only events of the base are removed
+        // even if an imported style has the same name
+        self.remove_replaced_events(merged_file);
+
+        ImportCounts {
+            dialogue_file: self
+                .event_import
+                .map_or(0, |event_import| event_import.apply(merged_file)),
+            sources: self
+                .sources
+                .iter()
+                .map(|source| source.event_import(self.style_overrides).apply(merged_file))
+                .collect(),
+        }
+    }
+
+    fn remove_replaced_events(&self, merged_file: &mut AssaFile) {
+        let replaced_kinds: Vec<EventKind> = self
+            .sources
+            .iter()
+            .flat_map(|source| source.kinds.iter().copied())
+            .collect();
+        if replaced_kinds.is_empty() {
+            return;
+        }
+        let base_classifications = classify_styles(
+            &self.base_file.events,
+            &classify_events(self.base_file),
+            self.style_overrides,
+        );
+        let replaced_styles = styles_of_kinds(&base_classifications, &replaced_kinds);
+        merged_file
+            .events
+            .retain(|event| event.comment || !replaced_styles.contains(&event.style));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alignment::sync_detection::SyncSegment;
+    use crate::test_helpers::event;
+    use chrono::{Duration, NaiveTime};
+
+    fn file(lines: &[&str]) -> AssaFile {
+        AssaFile {
+            events: lines.iter().map(|line| event(line)).collect(),
+            ..AssaFile::default()
+        }
+    }
+
+    #[test]
+    fn sources_replace_signs_of_the_base() {
+        let base_file = file(&[
+            "Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,Where are we going?",
+            r"Dialogue: 0,0:00:02.00,0:00:04.00,Sign,,0,0,0,,{\pos(100,50)}STATION",
+            "Dialogue: 0,0:00:05.00,0:00:07.00,Default,,0,0,0,,To the station.",
+        ]);
+        // Signs of the dialogue file share the style name with the base
+        let dialogue_file = file(&[
+            "Dialogue: 0,0:00:01.00,0:00:03.00,Default,,0,0,0,,Where to?",
+            r"Dialogue: 0,0:00:06.00,0:00:07.00,Sign,,0,0,0,,{\an8}Ticket office",
+        ]);
+        let signs_file = file(&[
+            "Dialogue: 0,0:00:11.00,0:00:13.00,Default,,0,0,0,,Where are we going?",
+            r"Dialogue: 0,0:00:12.00,0:00:14.00,Signs,,0,0,0,,{\pos(100,50)\blur1}Station",
+            r"Dialogue: 0,0:00:16.00,0:00:17.00,Signs,,0,0,0,,{\an8\pos(640,40)}Day 2",
+        ]);
+        let time = |time: &str| NaiveTime::parse_from_str(time, "%H:%M:%S%.f").unwrap();
+        let mut merged_file = base_file.clone();
+        let event_import = EventImport {
+            source_file: &dialogue_file,
+            indices: vec![1],
+            offset_map: OffsetMap { segments: vec![] },
+        };
+        // The signs file is 10 seconds late
+        let sources = [MergeSource {
+            file: &signs_file,
+            kinds: vec![EventKind::Sign],
+            offset_map: OffsetMap {
+                segments: vec![SyncSegment {
+                    start: time("0:00:00"),
+                    end: time("0:01:00"),
+                    offset: Duration::seconds(-10),
+                    anchor_count: 1,
+                }],
+            },
+        }];
+        let overrides = [
+            StyleOverride::from_str("Sign=sign").unwrap(),
+            StyleOverride::from_str("Signs=sign").unwrap(),
+        ];
+        let inputs = MergeInputs {
+            base_file: &base_file,
+            event_import: Some(&event_import),
+            sources: &sources,
+            style_overrides: &overrides,
+        };
+
+        assert_eq!(
+            inputs.import_events(&mut merged_file),
+            ImportCounts {
+                dialogue_file: 1,
+                sources: vec![2],
+            }
+        );
+        let events: Vec<(NaiveTime, &str)> = merged_file
+            .events
+            .iter()
+            .map(|event| (event.start, event.style.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (time("0:00:01"), "Default"),
+                (time("0:00:02"), "Signs"),
+                (time("0:00:05"), "Default"),
+                (time("0:00:06"), "Sign"),
+                (time("0:00:06"), "Signs"),
+            ]
+        );
+
+        assert_eq!(
+            SourceSpec::from_str("signs,songs=Show - 01.ass").unwrap(),
+            SourceSpec {
+                kinds: vec![EventKind::Sign, EventKind::Song],
+                path: PathBuf::from("Show - 01.ass"),
+            }
+        );
+        assert_eq!(
+            SourceSpec::from_str("dialogue=Show - 01.ass"),
+            Err(SourceError::UnsupportedKind(EventKind::Dialogue))
+        );
+    }
+}
@@ -0,0 +1,222 @@
+// Turns the styles of the dialogue file into base styles plus override tags
+//
+// Dialogue groups often have styles like Italics, Flashback or Top where the
+// base uses Default with {\i1} or {\an8}. The merged event keeps the style of
+// the base event, so what the dialogue style changed has to be carried over as
+// tags. Rules are written by hand (Italics=Default{\i1}, Top={\an8}) or found
+// by comparing each dialogue style with the main dialogue style.
+
+use std::str::FromStr;
+
+use assa_parse::assa_file::{
+    event::Event,
+    override_tags::{parse_text, OverrideTag, TextSegment},
+    style::Style,
+    AssaFile,
+};
+use thiserror::Error;
+
+/// Style differences carried over by automatic rules, fonts, sizes and
+/// colours are left to the base
+const AUTOMATIC_TAGS: [&str; 5] = ["i", "b", "u", "s", "an"];
+
+#[derive(Error, Debug, PartialEq)]
+pub enum StyleMappingError {
+    #[error("expected style=base style{{tags}}, got {0}")]
+    FormatError(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleRule {
+    pub dialogue_style: String,
+    /// Style the merged event gets, None keeps the style of the base event
+    pub base_style: Option<String>,
+    /// Tags put in front of the text of the dialogue event
+    pub tags: Vec<OverrideTag>,
+}
+
+/// Parses `Italics=Default{\i1}`, `Top={\an8}` and `Flashback=Flashback`
+impl FromStr for StyleRule {
+    type Err = StyleMappingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (dialogue_style, target) = s
+            .split_once('=')
+            .ok_or_else(|| StyleMappingError::FormatError(s.to_string()))?;
+        let (base_style, tags) = match target.find('{') {
+            Some(start) => match parse_text(&target[start..]).as_slice() {
+                [TextSegment::Tags(tags)] => (&target[..start], tags.clone()),
+                _ => return Err(StyleMappingError::FormatError(s.to_string())),
+            },
+            None => (target, Vec::new()),
+        };
+
+        Ok(StyleRule {
+            dialogue_style: dialogue_style.trim().to_string(),
+            base_style: Some(base_style.trim())
+                .filter(|base_style| !base_style.is_empty())
+                .map(|base_style| base_style.to_string()),
+            tags,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StyleMapping {
+    pub rules: Vec<StyleRule>,
+}
+
+impl StyleMapping {
+    /// Rules for the `dialogue_styles` of the file that differ from the most
+    /// used of them
+    pub fn automatic(dialogue_file: &AssaFile, dialogue_styles: &[String]) -> Self {
+        let main_style = dialogue_styles
+            .iter()
+            .max_by_key(|&style_name| {
+                dialogue_file
+                    .events
+                    .iter()
+                    .filter(|event| !event.comment && event.style == *style_name)
+                    .count()
+            })
+            .and_then(|style_name| find_style(dialogue_file, style_name));
+        let main_style = match main_style {
+            Some(main_style) => main_style,
+            None => return Self::default(),
+        };
+
+        let rules = dialogue_styles
+            .iter()
+            .filter_map(|style_name| find_style(dialogue_file, style_name))
+            .filter_map(|style| {
+                let tags: Vec<OverrideTag> = style
+                    .override_tags_from(main_style)
+                    .into_iter()
+                    .filter(|tag| AUTOMATIC_TAGS.contains(&tag.name.as_str()))
+                    .collect();
+                (!tags.is_empty()).then(|| StyleRule {
+                    dialogue_style: style.name.clone(),
+                    base_style: None,
+                    tags,
+                })
+            })
+            .collect();
+        Self { rules }
+    }
+
+    /// Rules of `rules` replace the rules for the same dialogue style
+    pub fn with_rules(mut self, rules: &[StyleRule]) -> Self {
+        self.rules.retain(|rule| {
+            !rules
+                .iter()
+                .any(|r| r.dialogue_style == rule.dialogue_style)
+        });
+        self.rules.extend(rules.iter().cloned());
+        self
+    }
+
+    pub fn rule(&self, dialogue_style: &str) -> Option<&StyleRule> {
+        self.rules
+            .iter()
+            .find(|rule| rule.dialogue_style == dialogue_style)
+    }
+
+    /// Copy of the event with the tags of its rule in front of the text
+    pub fn map_event(&self, event: &Event) -> Event {
+        let mut event = event.clone();
+        if let Some(rule) = self.rule(&event.style).filter(|rule| !rule.tags.is_empty()) {
+            let mut segments = event.text_segments();
+            match segments.first_mut() {
+                // Tags in the text win over the tags of the style
+                Some(TextSegment::Tags(tags)) => {
+                    let mut rule_tags: Vec<OverrideTag> = rule
+                        .tags
+                        .iter()
+                        .filter(|tag| !tags.iter().any(|t| t.name == tag.name))
+                        .cloned()
+                        .collect();
+                    rule_tags.append(tags);
+                    *tags = rule_tags;
+                }
+                _ => segments.insert(0, TextSegment::Tags(rule.tags.clone())),
+            }
+            event.text = segments.iter().map(|segment| segment.to_string()).collect();
+        }
+        event
+    }
+
+    /// Names of the tags the rules add, so they aren't filtered out again
+    pub fn tag_names(&self) -> Vec<String> {
+        let mut tag_names: Vec<String> = Vec::new();
+        for tag in self.rules.iter().flat_map(|rule| &rule.tags) {
+            if !tag_names.contains(&tag.name) {
+                tag_names.push(tag.name.clone());
+            }
+        }
+        tag_names
+    }
+}
+
+fn find_style<'a>(file: &'a AssaFile, style_name: &str) -> Option<&'a Style> {
+    file.styles.iter().find(|style| style.name == style_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::event;
+
+    #[test]
+    fn style_mapping_from_styles_and_rules() {
+        let dialogue_file = AssaFile {
+            styles: [
+                "Style: Main,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1",
+                "Style: Thoughts,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,-1,0,0,100,100,0,0,1,2,2,2,10,10,10,1",
+                "Style: Top,Verdana,44,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,8,10,10,10,1",
+            ]
+            .iter()
+            .map(|style| Style::from_str(style).unwrap())
+            .collect(),
+            events: [
+                "Dialogue: 0,0:00:01.00,0:00:02.00,Main,,0,0,0,,Hi.",
+                "Dialogue: 0,0:00:03.00,0:00:04.00,Main,,0,0,0,,Hello.",
+                "Dialogue: 0,0:00:05.00,0:00:06.00,Thoughts,,0,0,0,,{\\i0}Why me?",
+                "Dialogue: 0,0:00:07.00,0:00:08.00,Top,,0,0,0,,Up here.",
+            ]
+            .iter()
+            .map(|line| event(line))
+            .collect(),
+            ..AssaFile::default()
+        };
+        let styles: Vec<String> = ["Main", "Thoughts", "Top"]
+            .iter()
+            .map(|style| style.to_string())
+            .collect();
+
+        let style_mapping = StyleMapping::automatic(&dialogue_file, &styles);
+        let texts: Vec<String> = dialogue_file
+            .events
+            .iter()
+            .map(|event| style_mapping.map_event(event).text)
+            .collect();
+        assert_eq!(
+            texts,
+            vec!["Hi.", "Hello.", r"{\i0}Why me?", r"{\an8}Up here."]
+        );
+        assert_eq!(style_mapping.tag_names(), vec!["i", "an"]);
+
+        let style_mapping = style_mapping.with_rules(&[
+            StyleRule::from_str(r"Top=Default{\an8\b1}").unwrap(),
+            StyleRule::from_str("Main=Default").unwrap(),
+        ]);
+        assert_eq!(
+            style_mapping.map_event(&dialogue_file.events[3]).text,
+            r"{\an8\b1}Up here."
+        );
+        assert_eq!(
+            style_mapping.rule("Main").unwrap().base_style.as_deref(),
+            Some("Default")
+        );
+        assert!(StyleRule::from_str(r"Top={\an8}Text").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+// Decides which override tags end up in a merged event
+//
+// The base event knows where the line has to be shown (\an8 when signs cover
+// the bottom, \pos, \fad) and how the line is typeset (\blur), the dialogue
+// line knows which words are emphasised (\i1, \b1). Which tags are taken from
+// either side is set by a TagPolicy.
+
+use assa_parse::assa_file::override_tags::{parse_text, OverrideTag, TextSegment, LINE_TAGS};
+
+/// Tag names that are kept, `*` keeps all tags
+#[derive(Debug, Clone, PartialEq)]
+pub struct TagPolicy {
+    /// Tags of the base event that are copied to the start of the merged text
+    pub base_tags: Vec<String>,
+    /// Tags of the dialogue text that are kept, other tags are removed
+    pub dialogue_tags: Vec<String>,
+}
+
+impl Default for TagPolicy {
+    fn default() -> Self {
+        Self {
+            // Positioning and fades, plus the typesetting of the line
+            base_tags: LINE_TAGS
+                .iter()
+                .chain(["blur", "be", "i"].iter())
+                .map(|name| name.to_string())
+                .collect(),
+            dialogue_tags: ["i", "b", "u", "s"]
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+fn allows(tag_names: &[String], tag: &OverrideTag) -> bool {
+    tag_names
+        .iter()
+        .any(|name| name == "*" || *name == tag.name)
+}
+
+/// Returns the dialogue text with the tags the policy keeps from both the
+/// base and the dialogue text. Tags of the dialogue override base tags with
+/// the same name.
+pub fn transfer_tags(base_text: &str, dialogue_text: &str, policy: &TagPolicy) -> String {
+    let base_segments = parse_text(base_text);
+    let mut leading_tags: Vec<OverrideTag> = Vec::new();
+    for (i, segment) in base_segments.iter().enumerate() {
+        if let TextSegment::Tags(tags) = segment {
+            // Only line tags apply to the whole line when they're not at the start
+            leading_tags.extend(
+                tags.iter()
+                    .filter(|tag| (i == 0 || tag.is_line_tag()) && allows(&policy.base_tags, tag))
+                    .cloned(),
+            );
+        }
+    }
+
+    let mut dialogue_segments: Vec<TextSegment> = parse_text(dialogue_text)
+        .into_iter()
+        .filter_map(|segment| match segment {
+            TextSegment::Tags(tags) => {
+                let kept_tags: Vec<OverrideTag> = tags
+                    .into_iter()
+                    .filter(|tag| allows(&policy.dialogue_tags, tag))
+                    .collect();
+                (!kept_tags.is_empty()).then_some(TextSegment::Tags(kept_tags))
+            }
+            segment => Some(segment),
+        })
+        .collect();
+
+    if let Some(TextSegment::Tags(dialogue_tags)) = dialogue_segments.first() {
+        leading_tags.retain(|tag| !dialogue_tags.iter().any(|t| t.name == tag.name));
+        leading_tags.extend(dialogue_tags.iter().cloned());
+        dialogue_segments.remove(0);
+    }
+    if !leading_tags.is_empty() {
+        dialogue_segments.insert(0, TextSegment::Tags(leading_tags));
+    }
+
+    dialogue_segments
+        .iter()
+        .map(|segment| segment.to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfer_tags_keeps_base_position_and_dialogue_formatting() {
+        let policy = TagPolicy::default();
+
+        assert_eq!(
+            transfer_tags(
+                r"{\an8\fs40\blur0.6}What are you doing here?",
+                r"{\fs50}What {\i1}are{\i0} you doing here?",
+                &policy
+            ),
+            r"{\an8\blur0.6}What {\i1}are{\i0} you doing here?"
+        );
+        assert_eq!(
+            transfer_tags(r"{\i1}Hello?", r"{\i0}Hello, who's there?", &policy),
+            r"{\i0}Hello, who's there?"
+        );
+        assert_eq!(
+            transfer_tags(
+                r"{\an8}Hello?",
+                "Hello?",
+                &TagPolicy {
+                    base_tags: vec![],
+                    dialogue_tags: vec![String::from("*")],
+                }
+            ),
+            "Hello?"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+// Spreads one dialogue line over the base events it was split into
+//
+// Every way of splitting the line at punctuation and \N breaks is compared
+// with the durations of the base events, assuming the text is read at a
+// steady pace. The split whose parts match the share of time of each event
+// best wins, with a small preference for splits at the end of a sentence.
+
+use assa_parse::assa_file::{
+    event::Event,
+    override_tags::{parse_text, TextSegment},
+};
+
+use crate::alignment::text_processor::{
+    remove_styling, split_groups_at, split_indices_outside_tags,
+};
+
+/// Lines with more split points than this have too many ways to be split
+const MAX_SPLIT_POINTS: usize = 12;
+/// Bonus for every part that ends a sentence instead of a clause
+const SENTENCE_END_BONUS: f64 = 0.05;
+
+/// Effect set on the base events of a line that couldn't be split, so they
+/// can be found and checked by hand. The first one shows the whole line, the
+/// others are commented out.
+pub const UNSPLIT_EFFECT: &str = "unsplit";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DistributedText {
+    /// Text per base event, in the same order
+    pub parts: Vec<String>,
+    /// The line couldn't be split, the first event got all of it
+    pub fallback: bool,
+}
+
+pub fn distribute_text(text: &str, base_events: &[&Event]) -> DistributedText {
+    let part_count = base_events.len();
+    if part_count <= 1 {
+        return DistributedText {
+            parts: vec![text.to_string()],
+            fallback: false,
+        };
+    }
+
+    let split_indices = split_indices_outside_tags(text);
+    if split_indices.len() < part_count - 1 || split_indices.len() > MAX_SPLIT_POINTS {
+        return fallback(text, part_count);
+    }
+
+    let durations: Vec<f64> = base_events
+        .iter()
+        .map(|event| (event.end - event.start).num_milliseconds().max(0) as f64)
+        .collect();
+    let time_shares = shares(&durations);
+
+    split_groups_at(text, &split_indices)
+        .into_iter()
+        .filter(|group| group.len() == part_count)
+        .map(|group| {
+            let lengths: Vec<f64> = group
+                .iter()
+                .map(|part| remove_styling(part).chars().count() as f64)
+                .collect();
+            let deviation: f64 = shares(&lengths)
+                .iter()
+                .zip(&time_shares)
+                .map(|(text_share, time_share)| (text_share - time_share).abs())
+                .sum();
+            let sentence_ends = group[..part_count - 1]
+                .iter()
+                .filter(|part| ends_sentence(part))
+                .count();
+            (deviation - SENTENCE_END_BONUS * sentence_ends as f64, group)
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+        .map_or_else(
+            || fallback(text, part_count),
+            |(_, parts)| DistributedText {
+                parts: carry_leading_tags(parts),
+                fallback: false,
+            },
+        )
+}
+
+// Tags at the start of the line (e.g. {\i1} for a whole line in italics) are
+// only in the first part, the other parts are separate events now.
+fn carry_leading_tags(mut parts: Vec<String>) -> Vec<String> {
+    let leading_tags = match parse_text(&parts[0]).into_iter().next() {
+        Some(segment @ TextSegment::Tags(_)) => segment.to_string(),
+        _ => return parts,
+    };
+    for part in parts.iter_mut().skip(1) {
+        if !part.starts_with('{') {
+            part.insert_str(0, &leading_tags);
+        }
+    }
+    parts
+}
+
+fn fallback(text: &str, part_count: usize) -> DistributedText {
+    let mut parts = vec![String::new(); part_count];
+    parts[0] = text.to_string();
+    DistributedText {
+        parts,
+        fallback: true,
+    }
+}
+
+fn shares(values: &[f64]) -> Vec<f64> {
+    let total: f64 = values.iter().sum();
+    match total > 0f64 {
+        true => values.iter().map(|value| value / total).collect(),
+        false => vec![1f64 / values.len() as f64; values.len()],
+    }
+}
+
+fn ends_sentence(part: &str) -> bool {
+    remove_styling(part)
+        .trim_end_matches(['"', '\'', '」', '』'])
+        .ends_with(['.', '?', '!', '。', '？', '！', '…'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::dialogue;
+
+    fn event(start: f64, end: f64) -> Event {
+        dialogue(start, end, "Text")
+    }
+
+    #[test]
+    fn distribute_text_follows_event_durations() {
+        let text = "Wait, I forgot my bag at the station, can you come with me?";
+        let short_first = [&event(1.0, 1.6), &event(1.6, 5.0)];
+        let long_first = [&event(1.0, 4.0), &event(4.0, 5.5)];
+
+        assert_eq!(
+            distribute_text(text, &short_first).parts,
+            vec![
+                "Wait,",
+                "I forgot my bag at the station, can you come with me?"
+            ]
+        );
+        assert_eq!(
+            distribute_text(text, &long_first).parts,
+            vec![
+                "Wait, I forgot my bag at the station,",
+                "can you come with me?"
+            ]
+        );
+    }
+
+    #[test]
+    fn distribute_text_falls_back_to_first_event() {
+        let events = [&event(1.0, 2.0), &event(2.0, 3.0), &event(3.0, 4.0)];
+
+        let distributed_text = distribute_text("No way, really", &events);
+        assert!(distributed_text.fallback);
+        assert_eq!(distributed_text.parts, vec!["No way, really", "", ""]);
+    }
+
+    #[test]
+    fn distribute_text_carries_leading_tags() {
+        let events = [&event(1.0, 2.0), &event(2.0, 3.0)];
+
+        assert_eq!(
+            distribute_text(r"{\i1}Over here! Quickly!", &events).parts,
+            vec![r"{\i1}Over here!", r"{\i1}Quickly!"]
+        );
+    }
+
+    #[test]
+    fn distribute_text_keeps_override_blocks_whole() {
+        let events = [&event(1.0, 2.0), &event(2.0, 3.0)];
+
+        let distributed_text =
+            distribute_text(r"{\fad(150,150)}I told you I would come back home", &events);
+        assert!(distributed_text.fallback);
+        assert_eq!(
+            distributed_text.parts[0],
+            r"{\fad(150,150)}I told you I would come back home"
+        );
+        assert_eq!(
+            distribute_text(r"{\pos(320,50)}Wait for me, I'm coming too!", &events).parts,
+            vec![
+                r"{\pos(320,50)}Wait for me,",
+                r"{\pos(320,50)}I'm coming too!"
+            ]
+        );
+    }
+}
@@ -0,0 +1,281 @@
+// Joins several dialogue lines into the text of a single base event
+//
+// Lines that continue each other with an ellipsis or a dash are glued back
+// into one sentence, lines of different speakers become dash-prefixed turns on lines
+// of their own, and the override blocks the lines start with are combined
+// into one leading block.
+
+use assa_parse::assa_file::{
+    event::Event,
+    override_tags::{parse_text, segments_to_string, OverrideTag, TextSegment},
+};
+
+use crate::alignment::speaker::starts_with_turn_dash;
+
+/// Marks at the end of a line and the start of the next that continue it
+const CONTINUATIONS: [&str; 4] = ["...", "…", "—", "–"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinSeparator {
+    Space,
+    LineBreak,
+}
+
+impl JoinSeparator {
+    fn as_str(&self) -> &'static str {
+        match self {
+            JoinSeparator::Space => " ",
+            JoinSeparator::LineBreak => r"\N",
+        }
+    }
+}
+
+/// Joins the texts of the events, lines of different actors are treated as
+/// speaker turns.
+pub fn join_event_texts(events: &[&Event], separator: JoinSeparator) -> String {
+    let speaker_turns = events
+        .windows(2)
+        .any(|pair| !pair[0].name.is_empty() && pair[0].name != pair[1].name);
+    let texts: Vec<&str> = events.iter().map(|event| event.text.as_str()).collect();
+    join(&texts, separator, speaker_turns)
+}
+
+pub fn join_texts(texts: &[&str], separator: JoinSeparator) -> String {
+    join(texts, separator, false)
+}
+
+struct TextPart {
+    leading_tags: Vec<OverrideTag>,
+    body: String,
+}
+
+fn join(texts: &[&str], separator: JoinSeparator, speaker_turns: bool) -> String {
+    let parts: Vec<TextPart> = texts
+        .iter()
+        .filter(|text| !text.trim().is_empty())
+        .map(|text| split_leading_tags(text))
+        .collect();
+    if parts.is_empty() {
+        return String::new();
+    }
+
+    let speaker_turns = speaker_turns || parts.iter().any(|part| starts_with_turn_dash(&part.body));
+    let (leading_tags, part_tags) = reconcile_leading_tags(&parts);
+
+    let mut joined = tags_to_string(&leading_tags);
+    for (i, part) in parts.iter().enumerate() {
+        let mut body = part.body.as_str();
+        if i > 0 {
+            let continues = !speaker_turns
+                && CONTINUATIONS.iter().any(|mark| joined.ends_with(mark))
+                && CONTINUATIONS.iter().any(|mark| body.starts_with(mark));
+            if continues {
+                joined = CONTINUATIONS
+                    .iter()
+                    .find_map(|mark| joined.strip_suffix(mark))
+                    .unwrap()
+                    .trim_end()
+                    .to_string();
+                body = CONTINUATIONS
+                    .iter()
+                    .find_map(|mark| body.strip_prefix(mark))
+                    .unwrap()
+                    .trim_start();
+                joined.push(' ');
+            } else if speaker_turns {
+                joined.push_str(r"\N");
+            } else {
+                joined.push_str(separator.as_str());
+            }
+        }
+
+        joined.push_str(&tags_to_string(&part_tags[i]));
+        if speaker_turns && !starts_with_turn_dash(body) {
+            joined.push_str("- ");
+        }
+        joined.push_str(body);
+    }
+    joined
+}
+
+fn split_leading_tags(text: &str) -> TextPart {
+    let mut segments = parse_text(text.trim());
+    let leading_tags = match segments.first() {
+        Some(TextSegment::Tags(tags)) => {
+            let tags = tags.clone();
+            segments.remove(0);
+            tags
+        }
+        _ => Vec::new(),
+    };
+    TextPart {
+        leading_tags,
+        body: segments_to_string(&segments).trim().to_string(),
+    }
+}
+
+// Returns the tags for the start of the joined text and the tags to put in
+// front of each part. Line tags such as \an8 can only be set once, the first
+// part wins. Inline tags shared by all parts are set once at the start, the
+// others only for their own part and reset after it.
+fn reconcile_leading_tags(parts: &[TextPart]) -> (Vec<OverrideTag>, Vec<Vec<OverrideTag>>) {
+    let mut leading_tags: Vec<OverrideTag> = Vec::new();
+    for tag in parts.iter().flat_map(|part| &part.leading_tags) {
+        if tag.is_line_tag() && !leading_tags.iter().any(|t| t.name == tag.name) {
+            leading_tags.push(tag.clone());
+        }
+    }
+
+    let inline_tags: Vec<Vec<&OverrideTag>> = parts
+        .iter()
+        .map(|part| {
+            part.leading_tags
+                .iter()
+                .filter(|tag| !tag.is_line_tag())
+                .collect()
+        })
+        .collect();
+    let common_tags: Vec<&OverrideTag> = inline_tags[0]
+        .iter()
+        .filter(|tag| inline_tags.iter().all(|tags| tags.contains(tag)))
+        .copied()
+        .collect();
+    leading_tags.extend(common_tags.iter().map(|&tag| tag.clone()));
+
+    let own_tags: Vec<Vec<&OverrideTag>> = inline_tags
+        .iter()
+        .map(|tags| {
+            tags.iter()
+                .filter(|tag| !common_tags.contains(tag))
+                .copied()
+                .collect()
+        })
+        .collect();
+    let part_tags = own_tags
+        .iter()
+        .enumerate()
+        .map(|(i, tags)| {
+            let mut part_tags: Vec<OverrideTag> = Vec::new();
+            if i > 0 {
+                part_tags.extend(
+                    own_tags[i - 1]
+                        .iter()
+                        .filter(|previous| !tags.iter().any(|tag| tag.name == previous.name))
+                        .map(|previous| OverrideTag::new(&previous.name, "")),
+                );
+            }
+            part_tags.extend(tags.iter().map(|&tag| tag.clone()));
+            part_tags
+        })
+        .collect();
+
+    (leading_tags, part_tags)
+}
+
+fn tags_to_string(tags: &[OverrideTag]) -> String {
+    match tags.is_empty() {
+        true => String::new(),
+        false => TextSegment::Tags(tags.to_vec()).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::dialogue;
+
+    #[test]
+    fn join_texts_uses_separator() {
+        let texts = ["Hello?", "Are you there?"];
+        assert_eq!(
+            join_texts(&texts, JoinSeparator::Space),
+            "Hello? Are you there?"
+        );
+        assert_eq!(
+            join_texts(&texts, JoinSeparator::LineBreak),
+            r"Hello?\NAre you there?"
+        );
+    }
+
+    #[test]
+    fn join_texts_collapses_continuations() {
+        assert_eq!(
+            join_texts(
+                &["I was going to...", "...buy some bread."],
+                JoinSeparator::LineBreak
+            ),
+            "I was going to buy some bread."
+        );
+        assert_eq!(
+            join_texts(&["Well…", "… never mind."], JoinSeparator::Space),
+            "Well never mind."
+        );
+        assert_eq!(
+            join_texts(&["Well...", "Never mind."], JoinSeparator::Space),
+            "Well... Never mind."
+        );
+        // Interrupted lines, not speaker turns
+        assert_eq!(
+            join_texts(&["Well…", "…never mind."], JoinSeparator::LineBreak),
+            "Well never mind."
+        );
+        assert_eq!(
+            join_texts(&["I thought—", "—never mind."], JoinSeparator::Space),
+            "I thought never mind."
+        );
+        assert_eq!(
+            join_texts(
+                &["—And then he left.", "Just like that."],
+                JoinSeparator::Space
+            ),
+            "—And then he left. Just like that."
+        );
+    }
+
+    #[test]
+    fn join_texts_splits_speaker_turns() {
+        assert_eq!(
+            join_texts(&["-Who's there?", "-It's me!"], JoinSeparator::Space),
+            r"-Who's there?\N-It's me!"
+        );
+        assert_eq!(
+            join_texts(&["- Who's there?", "It's me!"], JoinSeparator::Space),
+            r"- Who's there?\N- It's me!"
+        );
+        assert_eq!(
+            join_texts(&["— Who's there?", "It's me!"], JoinSeparator::Space),
+            r"— Who's there?\N- It's me!"
+        );
+
+        let events: Vec<Event> = [("Mio", "Who's there?"), ("Ritsu", "It's me!")]
+            .iter()
+            .map(|(name, text)| Event {
+                name: name.to_string(),
+                ..dialogue(1.0, 2.0, text)
+            })
+            .collect();
+        assert_eq!(
+            join_event_texts(&events.iter().collect::<Vec<_>>(), JoinSeparator::Space),
+            r"- Who's there?\N- It's me!"
+        );
+    }
+
+    #[test]
+    fn join_texts_reconciles_leading_tags() {
+        assert_eq!(
+            join_texts(
+                &[r"{\an8\i1}Look!", r"{\i1}Up there!"],
+                JoinSeparator::Space
+            ),
+            r"{\an8\i1}Look! Up there!"
+        );
+        assert_eq!(
+            join_texts(&[r"{\i1}On the phone.", "I'm here."], JoinSeparator::Space),
+            r"{\i1}On the phone. {\i}I'm here."
+        );
+        assert_eq!(
+            join_texts(&[r"{\an8}Top.", r"{\an2\b1}Bottom."], JoinSeparator::Space),
+            r"{\an8}Top. {\b1}Bottom."
+        );
+    }
+}
@@ -0,0 +1,176 @@
+// Merging several translations of the same episode
+//
+// The dialogue sources are aligned with the base one by one (see
+// MultiAlignment) and the vote picks one translation per base line, in the
+// order the sources were given. The translations that lost can be added as
+// comments with the timing of the line, so an editor can compare them.
+
+use assa_parse::assa_file::{event::Event, AssaFile};
+
+use super::leftovers::{apply_unmatched_policy, carry_comment_events, TimeEstimate};
+use super::text_joining::{join_event_texts, JoinSeparator};
+use super::{merge_pairs, MergeConfig};
+use crate::alignment::aligned_pair::AlignedPair;
+use crate::alignment::alignment_plan::ScriptSelection;
+use crate::alignment::multi_alignment::MultiAlignment;
+use crate::alignment::AlignmentAction;
+
+/// Effect of the comments with the other translations, followed by the label
+/// of their source
+pub const ALTERNATIVE_EFFECT_PREFIX: &str = "alt: ";
+
+pub struct TranslationSource<'a> {
+    /// Shown in the effect of the alternative comments, e.g. the file name
+    pub label: String,
+    pub dialogue: ScriptSelection<'a>,
+}
+
+/// Copy of `base_file` with the translation the vote picked for every line.
+/// With several sources dialogue lines without a base line aren't added, the
+/// other sources would repeat them.
+pub fn merge_translations(
+    base_file: &AssaFile,
+    base: &ScriptSelection,
+    sources: &[TranslationSource],
+    alignment: &MultiAlignment,
+    min_confidence: f64,
+    show_alternatives: bool,
+    config: &MergeConfig,
+) -> AssaFile {
+    let mut merged_file = base_file.clone();
+    let chosen = alignment.vote(min_confidence);
+    for (source_index, source) in sources.iter().enumerate() {
+        let pairs = alignment.chosen_pairs(&chosen, source_index);
+        merge_pairs(
+            &mut merged_file,
+            base_file,
+            &source.dialogue,
+            base,
+            &pairs,
+            config,
+        );
+    }
+
+    let unmatched_pairs: Vec<AlignedPair> = (0..chosen.len())
+        .filter(|&i| chosen[i].is_none())
+        .map(|i| AlignedPair::new(vec![], vec![i], AlignmentAction::None))
+        .collect();
+    apply_unmatched_policy(
+        &mut merged_file,
+        base,
+        &unmatched_pairs,
+        config.unmatched_policy,
+    );
+
+    if show_alternatives {
+        let alternatives = alternative_comments(base_file, base, sources, alignment, &chosen);
+        merged_file.events.extend(alternatives);
+        merged_file.events.sort_by_key(|event| event.start);
+    }
+    if let (true, Some(primary)) = (config.carry_notes, sources.first()) {
+        let time_estimate =
+            TimeEstimate::from_pairs(base_file, &primary.dialogue, base, &alignment.sources[0]);
+        carry_comment_events(&mut merged_file, &primary.dialogue, &time_estimate);
+    }
+    merged_file
+}
+
+// A comment per base line and source that lost the vote, a pair that covers
+// several base lines is added once at its first line
+fn alternative_comments(
+    base_file: &AssaFile,
+    base: &ScriptSelection,
+    sources: &[TranslationSource],
+    alignment: &MultiAlignment,
+    chosen: &[Option<usize>],
+) -> Vec<Event> {
+    let mut comments: Vec<Event> = Vec::new();
+    for (base_index, chosen_source) in chosen.iter().enumerate() {
+        if chosen_source.is_none() {
+            continue;
+        }
+        for (source_index, source) in sources.iter().enumerate() {
+            let pair = match alignment.pair(base_index, source_index) {
+                Some(pair) if Some(source_index) != *chosen_source => pair,
+                _ => continue,
+            };
+            if pair.modified_indices.first() != Some(&base_index) {
+                continue;
+            }
+
+            let dialogue_events: Vec<&Event> = pair
+                .original_indices
+                .iter()
+                .map(|&i| &source.dialogue.events[source.dialogue.indices[i]])
+                .collect();
+            let mut comment = base_file.events[base.indices[base_index]].clone();
+            comment.comment = true;
+            comment.text = join_event_texts(&dialogue_events, JoinSeparator::Space);
+            comment.effect = format!("{}{}", ALTERNATIVE_EFFECT_PREFIX, source.label);
+            comments.push(comment);
+        }
+    }
+    comments
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_helpers::{dialogue_lines, pair};
+
+    #[test]
+    fn merge_translations_picks_a_line_per_source() {
+        let base_file = AssaFile {
+            events: dialogue_lines(&["Ohayou.", "Ikou."]),
+            ..AssaFile::default()
+        };
+        let first_events = dialogue_lines(&["Morning.", "Let's go."]);
+        let second_events = dialogue_lines(&["Good morning.", "Let us go."]);
+        let indices = [0, 1];
+        let selection = |events| ScriptSelection {
+            events,
+            indices: &indices,
+        };
+        let sources = [
+            TranslationSource {
+                label: String::from("first"),
+                dialogue: selection(&first_events),
+            },
+            TranslationSource {
+                label: String::from("second"),
+                dialogue: selection(&second_events),
+            },
+        ];
+        let alignment = MultiAlignment::new(
+            2,
+            vec![
+                vec![pair(vec![0], vec![0], 0.3), pair(vec![1], vec![1], 0.9)],
+                vec![pair(vec![0], vec![0], 0.8), pair(vec![1], vec![1], 0.9)],
+            ],
+        );
+
+        let merged_file = merge_translations(
+            &base_file,
+            &selection(&base_file.events),
+            &sources,
+            &alignment,
+            0.6,
+            true,
+            &MergeConfig::default(),
+        );
+        let events: Vec<(bool, &str, &str)> = merged_file
+            .events
+            .iter()
+            .map(|event| (event.comment, event.effect.as_str(), event.text.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                (false, "", "Good morning."),
+                (true, "alt: first", "Morning."),
+                (false, "", "Let's go."),
+                (true, "alt: second", "Let us go."),
+            ]
+        );
+    }
+}
@@ -0,0 +1,69 @@
+// Report of the aligned pairs QC should look at
+//
+// Lists every pair below the confidence threshold with the text and timing of
+// both sides, so only the risky lines have to be checked by hand.
+
+use std::fmt::Write;
+
+use assa_parse::assa_file::event::Event;
+
+use crate::alignment::aligned_pair::AlignedPair;
+
+pub fn low_confidence_pairs(pairs: &[AlignedPair], threshold: f64) -> Vec<&AlignedPair> {
+    pairs
+        .iter()
+        .filter(|pair| pair.confidence < threshold)
+        .collect()
+}
+
+pub fn low_confidence_report(
+    pairs: &[AlignedPair],
+    original_events: &[&Event],
+    modified_events: &[&Event],
+    threshold: f64,
+) -> String {
+    let review_pairs = low_confidence_pairs(pairs, threshold);
+    let mut report = format!(
+        "{} of {} aligned pairs below confidence {:.2}\n",
+        review_pairs.len(),
+        pairs.len(),
+        threshold
+    );
+
+    for pair in review_pairs {
+        let _ = writeln!(
+            report,
+            "\n[{:?}] confidence {:.2} (levenshtein {:.2}, semantic {:.2}, timing {:.2})",
+            pair.action,
+            pair.confidence,
+            pair.scores.levenshtein,
+            pair.scores.semantic,
+            pair.scores.timing
+        );
+        write_events(&mut report, "base", &pair.modified_indices, modified_events);
+        write_events(
+            &mut report,
+            "dialogue",
+            &pair.original_indices,
+            original_events,
+        );
+    }
+    report
+}
+
+fn write_events(report: &mut String, label: &str, indices: &[usize], events: &[&Event]) {
+    if indices.is_empty() {
+        let _ = writeln!(report, "  {:<8}  (unmatched)", label);
+    }
+    for &index in indices {
+        let event = events[index];
+        let _ = writeln!(
+            report,
+            "  {:<8}  {} - {}  {}",
+            label,
+            event.start.format("%H:%M:%S%.3f"),
+            event.end.format("%H:%M:%S%.3f"),
+            event.text
+        );
+    }
+}
@@ -0,0 +1,50 @@
+// Events and aligned pairs shared by the tests of the aligner and the merge
+
+use std::str::FromStr;
+
+use assa_parse::assa_file::event::Event;
+use chrono::{Duration, NaiveTime};
+
+use crate::alignment::{aligned_pair::AlignedPair, AlignmentAction};
+
+/// Parses a whole event line, `Dialogue: 0,0:00:01.00,...`
+pub fn event(line: &str) -> Event {
+    Event::from_str(line).unwrap()
+}
+
+/// Dialogue event of the Default style, times are in seconds. Other fields
+/// are set with struct update syntax, `Event { layer: 1, ..dialogue(..) }`.
+pub fn dialogue(start: f64, end: f64, text: &str) -> Event {
+    Event {
+        start: time(start),
+        end: time(end),
+        ..event(&format!(
+            "Dialogue: 0,0:00:00.00,0:00:00.00,Default,,0,0,0,,{}",
+            text
+        ))
+    }
+}
+
+/// One dialogue event per line, 3 seconds long and 4 seconds apart
+pub fn dialogue_lines(lines: &[&str]) -> Vec<Event> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, text)| dialogue(i as f64 * 4f64, i as f64 * 4f64 + 3f64, text))
+        .collect()
+}
+
+pub fn time(seconds: f64) -> NaiveTime {
+    NaiveTime::from_hms_opt(0, 0, 0).unwrap() + Duration::milliseconds((seconds * 1000f64) as i64)
+}
+
+pub fn pair(
+    original_indices: Vec<usize>,
+    modified_indices: Vec<usize>,
+    confidence: f64,
+) -> AlignedPair {
+    AlignedPair {
+        confidence,
+        ..AlignedPair::new(original_indices, modified_indices, AlignmentAction::None)
+    }
+}
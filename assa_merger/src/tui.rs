@@ -0,0 +1,177 @@
+// Terminal UI to review and correct an alignment by hand
+//
+// Shows the base and dialogue events of every aligned pair side by side. Every
+// edit pins the pairs involved, so saving the plan and running the alignment
+// again with --plan and --realign keeps the corrections.
+
+use std::{
+    io::{self, stdout, Stdout},
+    panic,
+    path::PathBuf,
+    sync::Arc,
+    thread,
+};
+
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEventKind},
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    ExecutableCommand,
+};
+use ratatui::{backend::CrosstermBackend, Terminal};
+
+use crate::alignment::aligned_pair::order_pairs;
+use crate::alignment::alignment_plan::AlignmentPlan;
+use crate::merge::{merge_dialogue, multi_source::MergeInputs, MergeConfig};
+
+pub mod app;
+mod ui;
+
+use app::{App, Side};
+
+/// Where the TUI saves its results
+pub struct TuiOutput<'a> {
+    pub inputs: MergeInputs<'a>,
+    pub merge_config: &'a MergeConfig,
+    pub plan_path: Option<PathBuf>,
+    pub merged_path: Option<PathBuf>,
+}
+
+pub fn run(app: &mut App, output: &TuiOutput) -> io::Result<()> {
+    // Dropped last, also when setting up the terminal fails halfway
+    let _guard = TerminalGuard::new();
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+
+    event_loop(&mut terminal, app, output)
+}
+
+/// Restores the terminal when dropped. Until then a panic restores it before
+/// the panic hook that was active before prints the message, which would
+/// otherwise end up hidden on the alternate screen with the shell in raw mode.
+struct TerminalGuard {
+    reinstall_hook: Option<Box<dyn FnOnce()>>,
+}
+
+impl TerminalGuard {
+    fn new() -> Self {
+        let previous_hook = Arc::new(panic::take_hook());
+        let hook = Arc::clone(&previous_hook);
+        panic::set_hook(Box::new(move |info| {
+            let _ = restore_terminal();
+            hook(info);
+        }));
+        Self {
+            reinstall_hook: Some(Box::new(move || {
+                panic::set_hook(Box::new(move |info| previous_hook(info)));
+            })),
+        }
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = restore_terminal();
+        // The hook can't be changed while panicking, the terminal was
+        // restored by it already
+        if !thread::panicking() {
+            if let Some(reinstall_hook) = self.reinstall_hook.take() {
+                reinstall_hook();
+            }
+        }
+    }
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+    Ok(())
+}
+
+fn event_loop(
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+    app: &mut App,
+    output: &TuiOutput,
+) -> io::Result<()> {
+    let mut quit_requested = false;
+    loop {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        app.status.clear();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => {
+                if !app.unsaved || quit_requested {
+                    return Ok(());
+                }
+                quit_requested = true;
+                app.status = String::from("Unsaved changes, press q again to quit");
+                continue;
+            }
+            KeyCode::Down | KeyCode::Char('j') => app.select_by(1),
+            KeyCode::Up | KeyCode::Char('k') => app.select_by(-1),
+            KeyCode::PageDown => app.select_by(10),
+            KeyCode::PageUp => app.select_by(-10),
+            KeyCode::Char('n') => app.select_next_for_review(),
+            KeyCode::Char('a') | KeyCode::Enter => app.accept(),
+            KeyCode::Char('x') => app.split(),
+            KeyCode::Char('m') => app.merge_with_next(),
+            KeyCode::Char('u') => app.unlink(),
+            KeyCode::Char('[') => app.give_to_next(Side::Base),
+            KeyCode::Char(']') => app.take_from_next(Side::Base),
+            KeyCode::Char('{') => app.give_to_next(Side::Dialogue),
+            KeyCode::Char('}') => app.take_from_next(Side::Dialogue),
+            KeyCode::Char('p') => save_plan(app, output),
+            KeyCode::Char('w') => write_merged_file(app, output),
+            _ => {}
+        }
+        quit_requested = false;
+    }
+}
+
+fn save_plan(app: &mut App, output: &TuiOutput) {
+    let plan_path = match &output.plan_path {
+        Some(plan_path) => plan_path,
+        None => {
+            app.status = String::from("No plan file given, start with --save-plan <file>");
+            return;
+        }
+    };
+
+    let mut pairs = app.pairs.clone();
+    order_pairs(&mut pairs);
+    app.status =
+        match AlignmentPlan::from_pairs(&pairs, &app.dialogue, &app.base).save_file(plan_path) {
+            Ok(()) => {
+                app.unsaved = false;
+                format!("Saved alignment plan to {}", plan_path.display())
+            }
+            Err(error) => format!("{}: {}", error, plan_path.display()),
+        };
+}
+
+fn write_merged_file(app: &mut App, output: &TuiOutput) {
+    let merged_path = match &output.merged_path {
+        Some(merged_path) => merged_path,
+        None => {
+            app.status = String::from("No output file given, start with --output <file>");
+            return;
+        }
+    };
+
+    let mut merged_file = merge_dialogue(
+        output.inputs.base_file,
+        &app.dialogue,
+        &app.base,
+        &app.pairs,
+        output.merge_config,
+    );
+    output.inputs.import_events(&mut merged_file);
+    app.status = match merged_file.save_file_as(&merged_path.to_string_lossy()) {
+        Ok(()) => format!("Wrote merged file to {}", merged_path.display()),
+        Err(error) => format!("{}: {}", error, merged_path.display()),
+    };
+}
@@ -0,0 +1,297 @@
+use assa_parse::assa_file::event::Event;
+
+use crate::alignment::aligned_pair::{AlignedPair, AlignmentScores};
+use crate::alignment::alignment_plan::ScriptSelection;
+use crate::alignment::AlignmentAction;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Dialogue,
+    Base,
+}
+
+pub struct App<'a> {
+    pub pairs: Vec<AlignedPair>,
+    pub dialogue: ScriptSelection<'a>,
+    pub base: ScriptSelection<'a>,
+    pub review_threshold: f64,
+    pub selected: usize,
+    pub status: String,
+    /// The pairs were edited since they were last saved
+    pub unsaved: bool,
+}
+
+impl<'a> App<'a> {
+    pub fn new(
+        pairs: Vec<AlignedPair>,
+        dialogue: ScriptSelection<'a>,
+        base: ScriptSelection<'a>,
+        review_threshold: f64,
+    ) -> Self {
+        Self {
+            pairs,
+            dialogue,
+            base,
+            review_threshold,
+            selected: 0,
+            status: String::new(),
+            unsaved: false,
+        }
+    }
+
+    pub fn dialogue_events(&self, pair: &AlignedPair) -> Vec<&Event> {
+        pair.original_indices
+            .iter()
+            .map(|&i| &self.dialogue.events[self.dialogue.indices[i]])
+            .collect()
+    }
+
+    pub fn base_events(&self, pair: &AlignedPair) -> Vec<&Event> {
+        pair.modified_indices
+            .iter()
+            .map(|&i| &self.base.events[self.base.indices[i]])
+            .collect()
+    }
+
+    pub fn needs_review(&self, pair: &AlignedPair) -> bool {
+        !pair.pinned && pair.confidence < self.review_threshold
+    }
+
+    pub fn review_count(&self) -> usize {
+        self.pairs
+            .iter()
+            .filter(|pair| self.needs_review(pair))
+            .count()
+    }
+
+    pub fn select_by(&mut self, steps: i32) {
+        let last_index = self.pairs.len().saturating_sub(1) as i32;
+        self.selected = (self.selected as i32 + steps).clamp(0, last_index) as usize;
+    }
+
+    pub fn select_next_for_review(&mut self) {
+        let next_index = (self.selected + 1..self.pairs.len())
+            .chain(0..self.selected)
+            .find(|&i| self.needs_review(&self.pairs[i]));
+        match next_index {
+            Some(index) => self.selected = index,
+            None => self.status = String::from("No pairs left to review"),
+        }
+    }
+
+    /// Confirms the selected pair as correct.
+    pub fn accept(&mut self) {
+        if let Some(pair) = self.pairs.get_mut(self.selected) {
+            mark_edited(pair);
+            self.unsaved = true;
+            self.select_by(1);
+        }
+    }
+
+    /// Breaks the selected pair up into unmatched lines.
+    pub fn unlink(&mut self) {
+        if self.selected >= self.pairs.len() {
+            return;
+        }
+
+        let pair = self.pairs.remove(self.selected);
+        let unlinked_pairs = pair
+            .original_indices
+            .iter()
+            .map(|&i| (vec![i], vec![]))
+            .chain(pair.modified_indices.iter().map(|&i| (vec![], vec![i])));
+        self.insert_edited(unlinked_pairs.collect());
+    }
+
+    /// Breaks the selected pair up into one-to-one pairs, lines left over on
+    /// either side end up unmatched.
+    pub fn split(&mut self) {
+        if self.selected >= self.pairs.len() {
+            return;
+        }
+
+        let pair = self.pairs.remove(self.selected);
+        let pair_count = pair.original_indices.len().max(pair.modified_indices.len());
+        let split_pairs = (0..pair_count).map(|i| {
+            (
+                pair.original_indices.get(i).into_iter().copied().collect(),
+                pair.modified_indices.get(i).into_iter().copied().collect(),
+            )
+        });
+        self.insert_edited(split_pairs.collect());
+    }
+
+    /// Combines the selected pair with the one below it.
+    pub fn merge_with_next(&mut self) {
+        if self.selected + 1 >= self.pairs.len() {
+            self.status = String::from("Nothing to merge with");
+            return;
+        }
+
+        let next_pair = self.pairs.remove(self.selected + 1);
+        let pair = &mut self.pairs[self.selected];
+        pair.original_indices.extend(next_pair.original_indices);
+        pair.modified_indices.extend(next_pair.modified_indices);
+        mark_edited(pair);
+        self.unsaved = true;
+    }
+
+    /// Moves the first line of the given side of the next pair into the
+    /// selected pair.
+    pub fn take_from_next(&mut self, side: Side) {
+        let next_index = self.selected + 1;
+        let next_indices = self
+            .pairs
+            .get_mut(next_index)
+            .map(|next_pair| side_indices(next_pair, side));
+        let taken_index = match next_indices {
+            Some(next_indices) if !next_indices.is_empty() => next_indices.remove(0),
+            _ => {
+                self.status = String::from("The next pair has no line to take");
+                return;
+            }
+        };
+
+        side_indices(&mut self.pairs[self.selected], side).push(taken_index);
+        mark_edited(&mut self.pairs[self.selected]);
+        self.finish_boundary_shift(next_index);
+    }
+
+    /// Moves the last line of the given side of the selected pair to the start
+    /// of the next pair.
+    pub fn give_to_next(&mut self, side: Side) {
+        let given_index = match self
+            .pairs
+            .get_mut(self.selected)
+            .and_then(|pair| side_indices(pair, side).pop())
+        {
+            Some(given_index) => given_index,
+            None => {
+                self.status = String::from("The selected pair has no line to give");
+                return;
+            }
+        };
+
+        let next_index = self.selected + 1;
+        if next_index == self.pairs.len() {
+            self.pairs
+                .push(AlignedPair::new(vec![], vec![], AlignmentAction::None));
+        }
+        side_indices(&mut self.pairs[next_index], side).insert(0, given_index);
+        mark_edited(&mut self.pairs[self.selected]);
+        self.finish_boundary_shift(next_index);
+    }
+
+    fn finish_boundary_shift(&mut self, next_index: usize) {
+        mark_edited(&mut self.pairs[next_index]);
+        if is_empty(&self.pairs[next_index]) {
+            self.pairs.remove(next_index);
+        }
+        if is_empty(&self.pairs[self.selected]) {
+            self.pairs.remove(self.selected);
+            self.select_by(0);
+        }
+        self.unsaved = true;
+    }
+
+    fn insert_edited(&mut self, index_groups: Vec<(Vec<usize>, Vec<usize>)>) {
+        for (offset, (original_indices, modified_indices)) in index_groups.into_iter().enumerate() {
+            let mut pair =
+                AlignedPair::new(original_indices, modified_indices, AlignmentAction::None);
+            mark_edited(&mut pair);
+            self.pairs.insert(self.selected + offset, pair);
+        }
+        self.select_by(0);
+        self.unsaved = true;
+    }
+}
+
+// Manual edits are pinned, so they are kept when the alignment is run again
+fn mark_edited(pair: &mut AlignedPair) {
+    let original_count = pair.original_indices.len();
+    let modified_count = pair.modified_indices.len();
+    pair.action = match (original_count, modified_count) {
+        (0, _) => AlignmentAction::Next,
+        (o, m) if o > m && m > 0 => AlignmentAction::Merge,
+        (o, m) if m > o => AlignmentAction::Split,
+        _ => AlignmentAction::None,
+    };
+    pair.scores = AlignmentScores::default();
+    pair.confidence = match pair.is_matched() {
+        true => 1f64,
+        false => 0f64,
+    };
+    pair.pinned = true;
+}
+
+fn side_indices(pair: &mut AlignedPair, side: Side) -> &mut Vec<usize> {
+    match side {
+        Side::Dialogue => &mut pair.original_indices,
+        Side::Base => &mut pair.modified_indices,
+    }
+}
+
+fn is_empty(pair: &AlignedPair) -> bool {
+    pair.original_indices.is_empty() && pair.modified_indices.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app<'a>(pairs: Vec<AlignedPair>) -> App<'a> {
+        let selection = || ScriptSelection {
+            events: &[],
+            indices: &[],
+        };
+        App::new(pairs, selection(), selection(), 0.6)
+    }
+
+    fn indices(app: &App) -> Vec<(Vec<usize>, Vec<usize>)> {
+        app.pairs
+            .iter()
+            .map(|pair| (pair.original_indices.clone(), pair.modified_indices.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn edits_regroup_lines_and_pin_pairs() {
+        let mut app = app(vec![
+            AlignedPair::new(vec![0], vec![0, 1], AlignmentAction::Split),
+            AlignedPair::new(vec![1], vec![2], AlignmentAction::None),
+            AlignedPair::new(vec![2], vec![], AlignmentAction::None),
+        ]);
+
+        app.give_to_next(Side::Base);
+        assert_eq!(
+            indices(&app),
+            vec![(vec![0], vec![0]), (vec![1], vec![1, 2]), (vec![2], vec![])]
+        );
+        assert_eq!(app.pairs[1].action, AlignmentAction::Split);
+        assert!(app.pairs[0].pinned && app.pairs[1].pinned);
+
+        app.select_by(1);
+        app.split();
+        assert_eq!(
+            indices(&app),
+            vec![
+                (vec![0], vec![0]),
+                (vec![1], vec![1]),
+                (vec![], vec![2]),
+                (vec![2], vec![])
+            ]
+        );
+
+        app.select_by(1);
+        app.merge_with_next();
+        assert_eq!(indices(&app)[2], (vec![2], vec![2]));
+        assert_eq!(app.pairs[2].confidence, 1f64);
+
+        app.unlink();
+        assert_eq!(
+            indices(&app)[2..],
+            vec![(vec![2], vec![]), (vec![], vec![2])]
+        );
+        assert!(app.unsaved);
+    }
+}
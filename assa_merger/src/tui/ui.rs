@@ -0,0 +1,92 @@
+use assa_parse::assa_file::event::Event;
+use ratatui::{
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Line, Text},
+    widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState},
+    Frame,
+};
+
+use super::app::App;
+
+const KEY_HELP: &str =
+    "j/k move  n next to review  a accept  x split  m merge with next  u unlink  \
+[/] give/take base line  {/} give/take dialogue line  p save plan  w write merged file  q quit";
+
+pub fn draw(frame: &mut Frame, app: &App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(3), Constraint::Length(3)])
+        .split(frame.size());
+
+    let rows = app.pairs.iter().enumerate().map(|(i, pair)| {
+        let base_text = event_lines(&app.base_events(pair));
+        let dialogue_text = event_lines(&app.dialogue_events(pair));
+        let height = base_text.height().max(dialogue_text.height()).max(1) as u16;
+
+        let style = if pair.pinned {
+            Style::default().fg(Color::Green)
+        } else if !pair.is_matched() {
+            Style::default().fg(Color::Red)
+        } else if app.needs_review(pair) {
+            Style::default().fg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+
+        Row::new(vec![
+            Cell::from((i + 1).to_string()),
+            Cell::from(format!("{:?}", pair.action)),
+            Cell::from(format!("{:.2}", pair.confidence)),
+            Cell::from(base_text),
+            Cell::from(dialogue_text),
+        ])
+        .height(height)
+        .style(style)
+    });
+
+    let title = format!(
+        " Alignment review: {} pairs, {} to review{} ",
+        app.pairs.len(),
+        app.review_count(),
+        if app.unsaved { ", unsaved changes" } else { "" }
+    );
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(5),
+            Constraint::Length(12),
+            Constraint::Length(5),
+            Constraint::Percentage(45),
+            Constraint::Percentage(45),
+        ],
+    )
+    .header(
+        Row::new(vec!["#", "Action", "Conf", "Base", "Dialogue"])
+            .style(Style::default().add_modifier(Modifier::BOLD)),
+    )
+    .block(Block::default().borders(Borders::ALL).title(title))
+    .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut table_state = TableState::default();
+    table_state.select(Some(app.selected));
+    frame.render_stateful_widget(table, chunks[0], &mut table_state);
+
+    let status = Paragraph::new(vec![Line::from(app.status.as_str()), Line::from(KEY_HELP)]);
+    frame.render_widget(status, chunks[1]);
+}
+
+fn event_lines(events: &[&Event]) -> Text<'static> {
+    Text::from(
+        events
+            .iter()
+            .map(|event| {
+                Line::from(format!(
+                    "{} {}",
+                    event.start.format("%H:%M:%S%.3f"),
+                    event.text
+                ))
+            })
+            .collect::<Vec<Line>>(),
+    )
+}
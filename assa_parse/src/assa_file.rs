@@ -16,9 +16,11 @@ use self::{project_garbage::MalformedProjectGarbageError, script_info::Malformed
 
 pub mod assa_colour;
 pub mod event;
+pub mod override_tags;
 pub mod project_garbage;
 pub mod script_info;
 pub mod style;
+pub mod style_merge;
 
 #[derive(Error, Debug)]
 pub enum MalformedAssaFileError {
@@ -30,6 +32,14 @@ pub enum MalformedAssaFileError {
     StyleError(MalformedStyleError),
     #[error("malformed Event string")]
     EventError(MalformedEventError),
+    #[error("could not read file")]
+    IoError(std::io::Error),
+}
+
+impl From<std::io::Error> for MalformedAssaFileError {
+    fn from(error: std::io::Error) -> Self {
+        MalformedAssaFileError::IoError(error)
+    }
 }
 
 impl From<MalformedScriptInfoError> for MalformedAssaFileError {
@@ -56,7 +66,7 @@ impl From<MalformedStyleError> for MalformedAssaFileError {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct AssaFile {
     pub script_info: ScriptInfo,
     pub styles: Vec<Style>,
@@ -67,12 +77,20 @@ pub struct AssaFile {
 
 impl AssaFile {
     pub fn from_file(ass_file_path: &str) -> Result<AssaFile, MalformedAssaFileError> {
+        let file_content = fs::read_to_string(ass_file_path)?;
+        AssaFile::from_str(&file_content)
+    }
+}
+
+impl FromStr for AssaFile {
+    type Err = MalformedAssaFileError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut assa_file = AssaFile::default();
-        let file_content = fs::read_to_string(ass_file_path).expect("Could not read file.");
 
         let re = Regex::new(r"(?s)(?m)\[(.*?)\](.*?)(\n\n|\r\n\r\n|\z)").unwrap(); // Matches data blocks (e.g. [Script Info], [Styles], [Events], [Aegisub Project Garbage])
 
-        for cap in re.captures_iter(&file_content) {
+        for cap in re.captures_iter(s) {
             println!("cap: '{}'", &cap[1]);
             match &cap[1] {
                 "Script Info" => assa_file.script_info = ScriptInfo::from_str(cap[0].trim())?,
@@ -91,8 +109,8 @@ impl AssaFile {
 }
 
 impl AssaFile {
-    pub fn save_file_as(&self, output_filepath: &str) {
-        let f = File::create(output_filepath).expect("fc");
+    pub fn save_file_as(&self, output_filepath: &str) -> std::io::Result<()> {
+        let f = File::create(output_filepath)?;
         let mut writer = BufWriter::new(f);
 
         let data = format!(
@@ -104,9 +122,8 @@ impl AssaFile {
             self.aegisub_extradata,
         );
 
-        writer
-            .write_all(data.as_bytes())
-            .expect("Unable to write data");
+        writer.write_all(data.as_bytes())?;
+        writer.flush()
     }
 }
 
@@ -159,3 +176,29 @@ Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour,
 
     export_string
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_str_test() {
+        let script = "[Script Info]
+ScriptType: v4.00+
+
+[V4+ Styles]
+Format: Name, Fontname, Fontsize, PrimaryColour, SecondaryColour, OutlineColour, BackColour, Bold, Italic, Underline, StrikeOut, ScaleX, ScaleY, Spacing, Angle, BorderStyle, Outline, Shadow, Alignment, MarginL, MarginR, MarginV, Encoding
+Style: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1
+
+[Events]
+Format: Layer, Start, End, Style, Name, MarginL, MarginR, MarginV, Effect, Text
+Dialogue: 0,0:00:01.00,0:00:04.00,Default,,0,0,0,,Wake up, it's already morning.
+Comment: 0,0:00:05.00,0:00:08.00,Default,,0,0,0,,TL note";
+
+        let assa_file = AssaFile::from_str(script).unwrap();
+        assert_eq!(assa_file.styles.len(), 1);
+        assert_eq!(assa_file.events.len(), 2);
+        assert_eq!(assa_file.events[0].text, "Wake up, it's already morning.");
+        assert!(assa_file.events[1].comment);
+    }
+}
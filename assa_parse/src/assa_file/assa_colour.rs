@@ -4,7 +4,7 @@
 
 use std::{fmt, str::FromStr};
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct AssaColour {
     /// Alpha channel is inverted, 255 = completely transparent and 0 is no transparency
     alpha: Option<u8>,
@@ -24,6 +24,11 @@ impl AssaColour {
         )
     }
 
+    /// Alpha channel, 0 when it's not set
+    pub fn alpha(&self) -> u8 {
+        self.alpha.unwrap_or(0)
+    }
+
     pub fn to_bgr(&self) -> String {
         format!("&H{:02X}{:02X}{:02X}", self.blue, self.green, self.red)
     }
@@ -22,6 +22,8 @@ use std::{
 use chrono::{naive::NaiveTime, Duration};
 use thiserror::Error;
 
+use super::override_tags::{parse_text, segments_to_string, OverrideTag, TextSegment};
+
 #[derive(Error, Debug)]
 pub enum MalformedEventError {
     #[error("malformed event string")]
@@ -48,14 +50,14 @@ impl From<chrono::ParseError> for MalformedEventError {
     }
 }
 
+// ASS timestamps have a single hour digit and centiseconds (0:00:01.50)
 fn format_time(time: &NaiveTime) -> String {
-    let mut time_str = time.to_string();
+    let mut time_str = time.format("%-H:%M:%S%.3f").to_string();
     time_str.pop();
-    time_str.remove(0);
     time_str
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone)]
 pub struct Event {
     pub comment: bool,
     pub layer: u8,
@@ -124,4 +126,60 @@ impl Event {
     pub fn duration(&self) -> Duration {
         self.end - self.start
     }
+
+    pub fn text_segments(&self) -> Vec<TextSegment> {
+        parse_text(&self.text)
+    }
+
+    /// Tags of the override block the text starts with
+    pub fn leading_tags(&self) -> Vec<OverrideTag> {
+        match self.text_segments().into_iter().next() {
+            Some(TextSegment::Tags(tags)) => tags,
+            _ => Vec::new(),
+        }
+    }
+
+    /// Scales the margins and override tags from one PlayRes to another
+    pub fn resample(&mut self, scale_x: f64, scale_y: f64) {
+        self.margin_l = (self.margin_l as f64 * scale_x).round() as u16;
+        self.margin_r = (self.margin_r as f64 * scale_x).round() as u16;
+        self.margin_v = (self.margin_v as f64 * scale_y).round() as u16;
+
+        let mut segments = self.text_segments();
+        for segment in &mut segments {
+            if let TextSegment::Tags(tags) = segment {
+                for tag in tags.iter_mut() {
+                    tag.resample(scale_x, scale_y);
+                }
+            }
+        }
+        self.text = segments_to_string(&segments);
+    }
+
+    /// The text without override blocks and comments
+    pub fn plain_text(&self) -> String {
+        self.text_segments()
+            .into_iter()
+            .filter_map(|segment| match segment {
+                TextSegment::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_test() {
+        // Whole seconds have no fraction in NaiveTime::to_string
+        for line in [
+            "Dialogue: 0,0:00:01.00,0:00:04.50,Default,,0,0,0,,Wake up.",
+            "Comment: 1,1:02:03.04,1:02:05.99,Sign,Mio,10,20,30,fade,{\\pos(10,20)}Note, two",
+        ] {
+            assert_eq!(Event::from_str(line).unwrap().to_string(), line);
+        }
+    }
 }
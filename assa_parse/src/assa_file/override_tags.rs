@@ -0,0 +1,291 @@
+// Code for parsing the override tags in the text of events
+// https://aegisub.org/docs/latest/ass_tags/
+
+// Example of event text with override tags:
+// {\an8\pos(960,80)}Top text {\i1}in italics{\i0} and {TL note: a comment}
+// {\t(0,500,\fs60)}growing text
+
+use std::fmt;
+use std::ops::Range;
+
+// Sorted so that longer names come before names they start with (fscx before
+// fs, iclip before i), the first match is the longest one.
+const TAG_NAMES: [&str; 52] = [
+    "xbord", "ybord", "xshad", "yshad", "alpha", "iclip", "blur", "bord", "clip", "fade", "fscx",
+    "fscy", "move", "shad", "fad", "fax", "fay", "frx", "fry", "frz", "fsp", "org", "pbo", "pos",
+    "1a", "2a", "3a", "4a", "1c", "2c", "3c", "4c", "an", "be", "fe", "fn", "fr", "fs", "kf", "ko",
+    "a", "b", "c", "i", "k", "K", "p", "q", "r", "s", "t", "u",
+];
+
+/// Tags that apply to the whole event, no matter where they are placed
+pub const LINE_TAGS: [&str; 10] = [
+    "an", "a", "pos", "move", "org", "clip", "iclip", "fad", "fade", "q",
+];
+
+/// A single override tag such as `\i1` or `\pos(320,50)`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverrideTag {
+    pub name: String,
+    /// Everything after the name, e.g. `1` or `(320,50)`. Empty values reset
+    /// the tag to the value of the style.
+    pub value: String,
+}
+
+impl OverrideTag {
+    pub fn new(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    fn parse(tag: &str) -> Self {
+        let name = TAG_NAMES
+            .iter()
+            .find(|name| tag.starts_with(*name))
+            .map_or_else(
+                || tag.chars().take_while(|c| c.is_alphabetic()).collect(),
+                |name| name.to_string(),
+            );
+        Self {
+            value: tag[name.len()..].to_string(),
+            name,
+        }
+    }
+
+    pub fn is_line_tag(&self) -> bool {
+        LINE_TAGS.contains(&self.name.as_str())
+    }
+
+    /// Scales coordinates and sizes from one PlayRes to another
+    pub fn resample(&mut self, scale_x: f64, scale_y: f64) {
+        let scale = |value: &str, factor: f64| match value.trim().parse::<f64>() {
+            Ok(number) => format_number(number * factor),
+            Err(_) => value.to_string(),
+        };
+
+        match self.name.as_str() {
+            "fs" | "bord" | "shad" | "blur" | "ybord" | "yshad" => {
+                self.value = scale(&self.value, scale_y)
+            }
+            "fsp" | "xbord" | "xshad" => self.value = scale(&self.value, scale_x),
+            "pos" | "org" | "move" => {
+                // \move has the times after the coordinates
+                let arguments: Vec<String> = self
+                    .arguments()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, argument)| match i {
+                        0..=3 => scale(argument, [scale_x, scale_y][i % 2]),
+                        _ => argument.to_string(),
+                    })
+                    .collect();
+                self.value = format!("({})", arguments.join(","));
+            }
+            "clip" | "iclip" => {
+                let mut arguments: Vec<String> =
+                    self.arguments().iter().map(|a| a.to_string()).collect();
+                match arguments.len() {
+                    4 => {
+                        for (i, argument) in arguments.iter_mut().enumerate() {
+                            *argument = scale(argument, [scale_x, scale_y][i % 2]);
+                        }
+                    }
+                    // Vector clip, optionally with a scale before the drawing
+                    _ => {
+                        if let Some(drawing) = arguments.last_mut() {
+                            *drawing = resample_drawing(drawing, scale_x, scale_y);
+                        }
+                    }
+                }
+                self.value = format!("({})", arguments.join(","));
+            }
+            "t" => {
+                if let Some(start) = self.value.find('\\') {
+                    let end = self.value.rfind(')').unwrap_or(self.value.len());
+                    let mut segments = parse_text(&format!("{{{}}}", &self.value[start..end]));
+                    if let Some(TextSegment::Tags(tags)) = segments.first_mut() {
+                        for tag in tags.iter_mut() {
+                            tag.resample(scale_x, scale_y);
+                        }
+                    }
+                    let animated_tags = segments_to_string(&segments);
+                    self.value = format!(
+                        "{}{}{}",
+                        &self.value[..start],
+                        &animated_tags[1..animated_tags.len() - 1],
+                        &self.value[end..]
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Arguments of tags like \pos(x,y), without parentheses
+    fn arguments(&self) -> Vec<&str> {
+        self.value
+            .trim()
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .split(',')
+            .collect()
+    }
+}
+
+fn resample_drawing(drawing: &str, scale_x: f64, scale_y: f64) -> String {
+    let mut is_x = true;
+    drawing
+        .split_whitespace()
+        .map(|part| match part.parse::<f64>() {
+            Ok(number) => {
+                let factor = if is_x { scale_x } else { scale_y };
+                is_x = !is_x;
+                format_number(number * factor)
+            }
+            Err(_) => {
+                is_x = true;
+                part.to_string()
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// Rounded to what renderers can tell apart, without trailing zeros
+fn format_number(number: f64) -> String {
+    let formatted = format!("{:.3}", number);
+    formatted
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+impl fmt::Display for OverrideTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\\{}{}", self.name, self.value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextSegment {
+    /// An override block, `{\i1\b1}`
+    Tags(Vec<OverrideTag>),
+    /// A block without tags, used for comments and notes, `{TL note}`
+    Comment(String),
+    Text(String),
+}
+
+impl fmt::Display for TextSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TextSegment::Tags(tags) => {
+                write!(f, "{{")?;
+                for tag in tags {
+                    write!(f, "{}", tag)?;
+                }
+                write!(f, "}}")
+            }
+            TextSegment::Comment(comment) => write!(f, "{{{}}}", comment),
+            TextSegment::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+pub fn parse_text(text: &str) -> Vec<TextSegment> {
+    parse_text_with_ranges(text)
+        .into_iter()
+        .map(|(_, segment)| segment)
+        .collect()
+}
+
+/// Segments of the text with their byte range in it, braces included
+pub fn parse_text_with_ranges(text: &str) -> Vec<(Range<usize>, TextSegment)> {
+    let mut segments: Vec<(Range<usize>, TextSegment)> = Vec::new();
+    let mut position = 0;
+
+    while position < text.len() {
+        let rest = &text[position..];
+        let block_start = rest.find('{');
+        let block_end =
+            block_start.and_then(|start| rest[start..].find('}').map(|end| start + end));
+        match (block_start, block_end) {
+            (Some(start), Some(end)) => {
+                if start > 0 {
+                    segments.push((
+                        position..position + start,
+                        TextSegment::Text(rest[..start].to_string()),
+                    ));
+                }
+                segments.push((
+                    position + start..position + end + 1,
+                    parse_block(&rest[start + 1..end]),
+                ));
+                position += end + 1;
+            }
+            // An unclosed brace is shown as text by renderers
+            _ => {
+                segments.push((position..text.len(), TextSegment::Text(rest.to_string())));
+                position = text.len();
+            }
+        }
+    }
+    segments
+}
+
+pub fn segments_to_string(segments: &[TextSegment]) -> String {
+    segments.iter().map(|segment| segment.to_string()).collect()
+}
+
+fn parse_block(block: &str) -> TextSegment {
+    if !block.starts_with('\\') {
+        return TextSegment::Comment(block.to_string());
+    }
+
+    // Backslashes within parentheses belong to the tag, e.g. \t(0,500,\fs60)
+    let mut tags: Vec<OverrideTag> = Vec::new();
+    let mut tag_start = 1;
+    let mut depth = 0;
+    for (i, c) in block.char_indices().skip(1) {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '\\' if depth <= 0 => {
+                tags.push(OverrideTag::parse(&block[tag_start..i]));
+                tag_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    tags.push(OverrideTag::parse(&block[tag_start..]));
+    tags.retain(|tag| !tag.name.is_empty() || !tag.value.is_empty());
+    TextSegment::Tags(tags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_text_test() {
+        let text = r"{\an8\fscx120\t(0,500,\fs60)}Top {\i1}text{\i0}{TL note}";
+        let segments = parse_text(text);
+
+        assert_eq!(
+            segments,
+            vec![
+                TextSegment::Tags(vec![
+                    OverrideTag::new("an", "8"),
+                    OverrideTag::new("fscx", "120"),
+                    OverrideTag::new("t", r"(0,500,\fs60)"),
+                ]),
+                TextSegment::Text(String::from("Top ")),
+                TextSegment::Tags(vec![OverrideTag::new("i", "1")]),
+                TextSegment::Text(String::from("text")),
+                TextSegment::Tags(vec![OverrideTag::new("i", "0")]),
+                TextSegment::Comment(String::from("TL note")),
+            ]
+        );
+        assert_eq!(segments_to_string(&segments), text);
+    }
+}
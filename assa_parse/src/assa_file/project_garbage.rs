@@ -28,6 +28,7 @@ impl From<ParseIntError> for MalformedProjectGarbageError {
     }
 }
 
+#[derive(Clone)]
 pub struct ProjectGarbage {
     pub last_style_storage: Option<String>,
     pub audio_file: Option<String>,
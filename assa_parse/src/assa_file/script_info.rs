@@ -50,6 +50,7 @@ impl From<ParseFloatError> for MalformedScriptInfoError {
     }
 }
 
+#[derive(Clone)]
 pub struct ScriptInfo {
     pub comments: Option<String>,
     pub title: Option<String>,
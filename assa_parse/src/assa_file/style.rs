@@ -17,7 +17,10 @@ use std::{
 
 use thiserror::Error;
 
-use super::assa_colour::{AssaColour, MalformedColourError};
+use super::{
+    assa_colour::{AssaColour, MalformedColourError},
+    override_tags::OverrideTag,
+};
 
 #[derive(Error, Debug, PartialEq)]
 pub enum MalformedStyleError {
@@ -47,32 +50,32 @@ impl From<ParseFloatError> for MalformedStyleError {
     }
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct Style {
-    name: String,
-    fontname: String,
-    fontsize: u8,
-    primary_colour: AssaColour,
-    secondary_colour: AssaColour,
-    outline_colour: AssaColour,
-    back_colour: AssaColour,
+    pub name: String,
+    pub fontname: String,
+    pub fontsize: u8,
+    pub primary_colour: AssaColour,
+    pub secondary_colour: AssaColour,
+    pub outline_colour: AssaColour,
+    pub back_colour: AssaColour,
     // For bold. italic, underline, and strike_out -1 is True, 0 is False (see ass specification)
-    bold: bool,
-    italic: bool,
-    underline: bool,
-    strike_out: bool,
-    scale_x: u16,
-    scale_y: u16,
-    spacing: f32,
-    angle: f32,
-    border_style: u8,
-    outline: f32,
-    shadow: f32,
-    alignment: u8,
-    margin_l: u16,
-    margin_r: u16,
-    margin_v: u16,
-    encoding: u8,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike_out: bool,
+    pub scale_x: u16,
+    pub scale_y: u16,
+    pub spacing: f32,
+    pub angle: f32,
+    pub border_style: u8,
+    pub outline: f32,
+    pub shadow: f32,
+    pub alignment: u8,
+    pub margin_l: u16,
+    pub margin_r: u16,
+    pub margin_v: u16,
+    pub encoding: u8,
 }
 
 impl fmt::Display for Style {
@@ -142,10 +145,10 @@ impl FromStr for Style {
             secondary_colour: AssaColour::from_str(style_values[4])?,
             outline_colour: AssaColour::from_str(style_values[5])?,
             back_colour: AssaColour::from_str(style_values[6])?,
-            bold: style_values[7] == "-1",
-            italic: style_values[8] == "1",
-            underline: style_values[9] == "1",
-            strike_out: style_values[10] == "1",
+            bold: matches!(style_values[7], "-1" | "1"),
+            italic: matches!(style_values[8], "-1" | "1"),
+            underline: matches!(style_values[9], "-1" | "1"),
+            strike_out: matches!(style_values[10], "-1" | "1"),
             scale_x: style_values[11].parse()?,
             scale_y: style_values[12].parse()?,
             spacing: style_values[13].parse()?,
@@ -162,6 +165,99 @@ impl FromStr for Style {
     }
 }
 
+impl Style {
+    /// Override tags that make text in the `base` style look like this style.
+    /// Margins, border style and encoding can't be set with tags.
+    pub fn override_tags_from(&self, base: &Style) -> Vec<OverrideTag> {
+        let mut tags: Vec<OverrideTag> = Vec::new();
+        let mut add_tag = |differs: bool, name: &str, value: String| {
+            if differs {
+                tags.push(OverrideTag::new(name, &value));
+            }
+        };
+        let flag = |value: bool| String::from(if value { "1" } else { "0" });
+
+        add_tag(self.fontname != base.fontname, "fn", self.fontname.clone());
+        add_tag(
+            self.fontsize != base.fontsize,
+            "fs",
+            self.fontsize.to_string(),
+        );
+        let colours = [
+            (&self.primary_colour, &base.primary_colour),
+            (&self.secondary_colour, &base.secondary_colour),
+            (&self.outline_colour, &base.outline_colour),
+            (&self.back_colour, &base.back_colour),
+        ];
+        for (i, (colour, base_colour)) in colours.into_iter().enumerate() {
+            add_tag(
+                colour.to_bgr() != base_colour.to_bgr(),
+                &format!("{}c", i + 1),
+                format!("{}&", colour.to_bgr()),
+            );
+            add_tag(
+                colour.alpha() != base_colour.alpha(),
+                &format!("{}a", i + 1),
+                format!("&H{:02X}&", colour.alpha()),
+            );
+        }
+        add_tag(self.bold != base.bold, "b", flag(self.bold));
+        add_tag(self.italic != base.italic, "i", flag(self.italic));
+        add_tag(self.underline != base.underline, "u", flag(self.underline));
+        add_tag(
+            self.strike_out != base.strike_out,
+            "s",
+            flag(self.strike_out),
+        );
+        add_tag(
+            self.scale_x != base.scale_x,
+            "fscx",
+            self.scale_x.to_string(),
+        );
+        add_tag(
+            self.scale_y != base.scale_y,
+            "fscy",
+            self.scale_y.to_string(),
+        );
+        add_tag(
+            self.spacing != base.spacing,
+            "fsp",
+            self.spacing.to_string(),
+        );
+        add_tag(self.angle != base.angle, "frz", self.angle.to_string());
+        add_tag(
+            self.outline != base.outline,
+            "bord",
+            self.outline.to_string(),
+        );
+        add_tag(self.shadow != base.shadow, "shad", self.shadow.to_string());
+        add_tag(
+            self.alignment != base.alignment,
+            "an",
+            self.alignment.to_string(),
+        );
+        tags
+    }
+
+    /// Scales the sizes of the style from one PlayRes to another. Font sizes
+    /// follow the height, a change of aspect ratio stretches the font
+    /// horizontally.
+    pub fn resample(&mut self, scale_x: f64, scale_y: f64) {
+        self.fontsize = (self.fontsize as f64 * scale_y)
+            .round()
+            .clamp(1f64, u8::MAX as f64) as u8;
+        if scale_y > 0f64 {
+            self.scale_x = (self.scale_x as f64 * scale_x / scale_y).round() as u16;
+        }
+        self.spacing = (self.spacing as f64 * scale_x) as f32;
+        self.outline = (self.outline as f64 * scale_y) as f32;
+        self.shadow = (self.shadow as f64 * scale_y) as f32;
+        self.margin_l = (self.margin_l as f64 * scale_x).round() as u16;
+        self.margin_r = (self.margin_r as f64 * scale_x).round() as u16;
+        self.margin_v = (self.margin_v as f64 * scale_y).round() as u16;
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -192,4 +288,18 @@ mod tests {
             MalformedStyleError::ParseError
         );
     }
+
+    #[test]
+    fn override_tags_from_test() {
+        let default = Style::from_str("Style: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1").unwrap();
+        let top_italics = Style::from_str("Style: Top,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H80000000,0,1,0,0,100,100,0,0,1,2,2,8,10,10,10,1").unwrap();
+
+        let tags: Vec<String> = top_italics
+            .override_tags_from(&default)
+            .iter()
+            .map(|tag| tag.to_string())
+            .collect();
+        assert_eq!(tags, vec![r"\4a&H80&", r"\i1", r"\an8"]);
+        assert!(default.override_tags_from(&default).is_empty());
+    }
 }
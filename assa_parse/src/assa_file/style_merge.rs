@@ -0,0 +1,242 @@
+// Code for copying styles from one ASS/SSA file into another
+//
+// Styles with the same name and definition are shared, a style whose name is
+// taken by a different definition gets a suffix ("Signs" becomes
+// "Signs (import)"). Events moved between the files have to be passed through
+// StyleMerge::apply so their style and \r tags point to the new names, and so
+// their positions and sizes match the PlayRes of the target file.
+
+use std::collections::HashMap;
+
+use super::{
+    event::Event,
+    override_tags::{segments_to_string, TextSegment},
+    script_info::ScriptInfo,
+    style::Style,
+    AssaFile,
+};
+
+/// Renderers use this style for events with an unknown style
+const FALLBACK_STYLE: &str = "Default";
+
+/// How the styles of a source file map onto a target file
+#[derive(Debug, Clone, PartialEq)]
+pub struct StyleMerge {
+    /// Source style names that got a different name in the target file
+    pub renamed: HashMap<String, String>,
+    /// Factors from the PlayRes of the source file to the target file, None if
+    /// they're the same
+    pub scale: Option<(f64, f64)>,
+}
+
+impl StyleMerge {
+    pub fn style_name<'a>(&'a self, name: &'a str) -> &'a str {
+        self.renamed.get(name).map_or(name, |name| name.as_str())
+    }
+
+    /// Points the event to the styles in the target file and resamples it
+    pub fn apply(&self, event: &mut Event) {
+        event.style = self.style_name(&event.style).to_string();
+
+        let mut segments = event.text_segments();
+        for segment in &mut segments {
+            if let TextSegment::Tags(tags) = segment {
+                for tag in tags.iter_mut().filter(|tag| tag.name == "r") {
+                    tag.value = self.style_name(&tag.value).to_string();
+                }
+            }
+        }
+        event.text = segments_to_string(&segments);
+
+        if let Some((scale_x, scale_y)) = self.scale {
+            event.resample(scale_x, scale_y);
+        }
+    }
+}
+
+/// Names of the styles the events use, including styles switched to with \r
+pub fn used_styles<'a>(events: impl IntoIterator<Item = &'a Event>) -> Vec<String> {
+    let mut style_names: Vec<String> = Vec::new();
+    for event in events {
+        let reset_styles = event
+            .text_segments()
+            .into_iter()
+            .filter_map(|segment| match segment {
+                TextSegment::Tags(tags) => Some(tags),
+                _ => None,
+            })
+            .flatten()
+            .filter(|tag| tag.name == "r" && !tag.value.is_empty())
+            .map(|tag| tag.value);
+        for style_name in std::iter::once(event.style.clone()).chain(reset_styles) {
+            if !style_names.contains(&style_name) {
+                style_names.push(style_name);
+            }
+        }
+    }
+    style_names
+}
+
+/// PlayResX and PlayResY, filled in the way renderers do when they're missing
+pub fn play_res(script_info: &ScriptInfo) -> (f64, f64) {
+    match (script_info.play_res_x, script_info.play_res_y) {
+        (Some(x), Some(y)) => (x as f64, y as f64),
+        (Some(1280), None) => (1280f64, 1024f64),
+        (Some(x), None) => (x as f64, x as f64 * 3f64 / 4f64),
+        (None, Some(1024)) => (1280f64, 1024f64),
+        (None, Some(y)) => (y as f64 * 4f64 / 3f64, y as f64),
+        (None, None) => (384f64, 288f64),
+    }
+}
+
+impl AssaFile {
+    /// Copies the styles named `style_names` from `source` into this file.
+    /// Conflicting styles are renamed with `suffix`, styles missing from
+    /// `source` get the definition of its Default style.
+    pub fn merge_styles(
+        &mut self,
+        source: &AssaFile,
+        style_names: &[String],
+        suffix: &str,
+    ) -> StyleMerge {
+        let (source_x, source_y) = play_res(&source.script_info);
+        let (target_x, target_y) = play_res(&self.script_info);
+        let scale = match (source_x, source_y) == (target_x, target_y) {
+            true => None,
+            false => Some((target_x / source_x, target_y / source_y)),
+        };
+        let mut style_merge = StyleMerge {
+            renamed: HashMap::new(),
+            scale,
+        };
+
+        for name in style_names {
+            let mut style = match source
+                .styles
+                .iter()
+                .find(|style| style.name == *name)
+                .or_else(|| {
+                    source
+                        .styles
+                        .iter()
+                        .find(|style| style.name == FALLBACK_STYLE)
+                }) {
+                Some(style) => style.clone(),
+                None => continue,
+            };
+            style.name = name.clone();
+            if let Some((scale_x, scale_y)) = scale {
+                style.resample(scale_x, scale_y);
+            }
+
+            let mut new_name = name.clone();
+            let mut attempt = 1;
+            loop {
+                match self.styles.iter().find(|target| target.name == new_name) {
+                    None => {
+                        style.name = new_name.clone();
+                        self.styles.push(style);
+                        break;
+                    }
+                    Some(target) if same_definition(target, &style) => break,
+                    Some(_) => {
+                        new_name = match attempt {
+                            1 => format!("{}{}", name, suffix),
+                            _ => format!("{}{} {}", name, suffix, attempt),
+                        };
+                        attempt += 1;
+                    }
+                }
+            }
+            if new_name != *name {
+                style_merge.renamed.insert(name.clone(), new_name);
+            }
+        }
+        style_merge
+    }
+}
+
+/// Whether the styles look the same, the names may differ
+pub fn same_definition(style: &Style, other: &Style) -> bool {
+    Style {
+        name: other.name.clone(),
+        ..style.clone()
+    } == *other
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn file(play_res: u16, styles: &[&str]) -> AssaFile {
+        AssaFile {
+            script_info: ScriptInfo {
+                play_res_x: Some(play_res * 16 / 9),
+                play_res_y: Some(play_res),
+                ..ScriptInfo::default()
+            },
+            styles: styles
+                .iter()
+                .map(|style| Style::from_str(style).unwrap())
+                .collect(),
+            ..AssaFile::default()
+        }
+    }
+
+    #[test]
+    fn merge_styles_renames_conflicts() {
+        let default = "Style: Default,Arial,48,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,2,10,10,10,1";
+        let signs = "Style: Signs,Arial,40,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,8,10,10,10,1";
+        let other_signs = "Style: Signs,Verdana,40,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,2,2,8,10,10,10,1";
+        let source = file(1080, &[default, other_signs]);
+        let mut target = file(1080, &[default, signs]);
+
+        let mut event = Event::from_str(
+            r"Dialogue: 0,0:00:01.00,0:00:02.00,Signs,,0,0,0,,{\pos(10,20)}Cafe{\rTitle}Open",
+        )
+        .unwrap();
+        let style_names = used_styles([&event]);
+        assert_eq!(style_names, vec!["Signs", "Title"]);
+
+        let style_merge = target.merge_styles(&source, &style_names, " (import)");
+        style_merge.apply(&mut event);
+        assert_eq!(event.style, "Signs (import)");
+        assert_eq!(event.text, r"{\pos(10,20)}Cafe{\rTitle}Open");
+        let names: Vec<&str> = target
+            .styles
+            .iter()
+            .map(|style| style.name.as_str())
+            .collect();
+        // Title is missing from the source, it's shown with its Default style
+        assert_eq!(names, vec!["Default", "Signs", "Signs (import)", "Title"]);
+        assert_eq!(
+            target.merge_styles(&source, &[String::from("Default")], " (import)"),
+            StyleMerge {
+                renamed: HashMap::new(),
+                scale: None
+            }
+        );
+    }
+
+    #[test]
+    fn merge_styles_resamples_to_play_res() {
+        let default = "Style: Default,Arial,24,&H00FFFFFF,&H000000FF,&H00000000,&H00000000,0,0,0,0,100,100,0,0,1,1,1,2,10,10,15,1";
+        let source = file(360, &[default]);
+        let mut target = file(1080, &[]);
+
+        let mut event = Event::from_str(
+            r"Dialogue: 0,0:00:01.00,0:00:02.00,Default,,0,0,0,,{\move(10,20,110,20,0,500)\fs30\t(0,500,\fs20)\clip(m 0 0 l 10 0 10 10)}Sign",
+        )
+        .unwrap();
+        let style_merge = target.merge_styles(&source, &used_styles([&event]), " (import)");
+        style_merge.apply(&mut event);
+
+        assert_eq!(target.styles[0].fontsize, 72);
+        assert_eq!(target.styles[0].margin_v, 45);
+        assert_eq!(
+            event.text,
+            r"{\move(30,60,330,60,0,500)\fs90\t(0,500,\fs60)\clip(m 0 0 l 30 0 30 30)}Sign"
+        );
+    }
+}
@@ -18,5 +18,5 @@ fn main() {
     // println!("{}", assa_file.styles[3]);
     println!("'{}'", assa_file.aegisub_extradata);
 
-    assa_file.save_file_as("./test.ass");
+    assa_file.save_file_as("./test.ass").unwrap();
 }